@@ -0,0 +1,25 @@
+use std::hint::black_box;
+use std::time::Instant;
+
+use irk::{proto::tags::TagIter, Message};
+
+const LINE: &str = "@account=rini;batch=abc;label=x1;msgid=63E1033A051D4B41;+draft/reply=x0;\
+                    time=2024-01-01T00:00:00.000Z :rini!rini@example.com PRIVMSG #irk :hello world";
+const ROUNDS: u32 = 1_000_000;
+
+fn bench(name: &str, f: impl Fn() -> bool) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(f());
+    }
+    println!("{name:>8}: {:?}/iter", start.elapsed() / ROUNDS);
+}
+
+fn main() {
+    bench("eager", || {
+        Message::from(black_box(LINE)).tag("msgid").is_some()
+    });
+    bench("lazy", || {
+        TagIter::from_line(black_box(LINE)).get("msgid").is_some()
+    });
+}
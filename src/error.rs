@@ -3,7 +3,13 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     Eof,
+    MissingField { name: &'static str, at: usize },
     UnsupportedType,
+    IllegalChar(char),
+    InvalidCommand,
+    InvalidTagKey,
+    LineTooLong(usize),
+    TagsTooLong(usize),
     InvalidUtf8(std::str::Utf8Error),
     Serialize(String),
     Deserialize(String),
@@ -13,7 +19,17 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Eof => write!(f, "Unexpected end of input"),
+            Self::MissingField { name, at } => {
+                write!(f, "Missing field `{name}` at parameter {at}")
+            }
             Self::UnsupportedType => write!(f, "Unsupported type"),
+            Self::IllegalChar(c) => write!(f, "Illegal character {c:?} in parameter"),
+            Self::InvalidCommand => write!(f, "Command must be a non-empty word"),
+            Self::InvalidTagKey => {
+                write!(f, "Tag key must be a name with an optional `+` and vendor")
+            }
+            Self::LineTooLong(len) => write!(f, "Line is {len} bytes long"),
+            Self::TagsTooLong(len) => write!(f, "Tags are {len} bytes long"),
             Self::InvalidUtf8(e) => write!(f, "Invalid utf8 in input: {e}"),
             Self::Serialize(e) => write!(f, "Serialize error: {e}"),
             Self::Deserialize(e) => write!(f, "Deserialize error: {e}"),
@@ -2,17 +2,454 @@ pub mod error;
 pub mod proto;
 
 pub use error::{Error, Result};
-pub use proto::{ser::Serializer, Command};
+pub use proto::{ser::Serializer, Command, OwnedCommand};
 
-#[derive(Debug, Default, PartialEq, Eq)]
+use std::{borrow::Cow, ops::Range};
+
+// everything but the tags, including the CRLF
+pub const MAX_LINE_LEN: usize = 512;
+
+#[derive(Debug, Default)]
 pub struct Message<'a> {
+    pub tags: Vec<(&'a str, &'a str)>,
     pub source: Option<&'a str>,
     pub command: &'a str,
     pub parameters: Vec<&'a str>,
+    pub trailing: bool,
+    // the line this was lexed from, empty when built by hand
+    pub raw: &'a str,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct MessageSpans {
+    pub tags: Option<Range<usize>>,
+    pub source: Option<Range<usize>>,
+    pub command: Range<usize>,
+    pub middle: Option<Range<usize>>,
+    pub trailing: Option<Range<usize>>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct MessageBuf {
+    pub tags: Vec<(String, String)>,
+    pub source: Option<String>,
+    pub command: String,
+    pub parameters: Vec<String>,
+    pub trailing: bool,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum LineEnding {
+    #[default]
+    Crlf,
+    Lf,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct OwnedMessage {
+    line: String,
+}
+
+impl<'a> Message<'a> {
+    pub fn from_parts(source: Option<&'a str>, command: &'a str, params: &[&'a str]) -> Self {
+        Self {
+            source,
+            command,
+            parameters: params.to_vec(),
+            trailing: params
+                .last()
+                .is_some_and(|p| p.is_empty() || p.contains(' ') || p.starts_with(':')),
+            ..Default::default()
+        }
+    }
+
+    // for servers that leave the colon off message bodies: once `middle(command)` parameters are
+    // read, the rest of the line is the trailing parameter whether it starts with `:` or not
+    pub fn parse_lenient(line: &'a str, middle: fn(&str) -> Option<usize>) -> Self {
+        let mut lexer = Lexer::new(line.strip_prefix('\u{feff}').unwrap_or(line));
+        lexer.middle = middle;
+        // `raw` keeps the BOM, so spans are offsets into the line as it was passed in
+        Message {
+            raw: line,
+            ..lexer.parse()
+        }
+    }
+
+    pub fn parse_strict(line: &'a str) -> Result<Self> {
+        if let Some(c) = line
+            .chars()
+            .find(|c| ['\u{feff}', '\0', '\r', '\n'].contains(c))
+        {
+            return Err(Error::IllegalChar(c));
+        }
+        Ok(Lexer::new(line).parse())
+    }
+
+    pub fn nested(&self) -> Result<Message<'a>> {
+        let line = self.parameters.last().ok_or(Error::Eof)?;
+        match Message::from(*line) {
+            msg if msg.command.is_empty() => Err(Error::Eof),
+            msg => Ok(msg),
+        }
+    }
+
+    pub fn strip_tags(&self) -> Message<'a> {
+        Message {
+            tags: Vec::new(),
+            source: self.source,
+            command: self.command,
+            parameters: self.parameters.clone(),
+            trailing: self.trailing,
+            raw: "",
+        }
+    }
+}
+
+impl Message<'_> {
+    pub fn tag(&self, key: &str) -> Option<Cow<'_, str>> {
+        let (_, value) = self.tags.iter().find(|(k, _)| *k == key)?;
+        Some(proto::tags::unescape(value))
+    }
+
+    pub fn with_tag(&self, key: &str, value: &str) -> Result<MessageBuf> {
+        let mut buf = MessageBuf::from(self);
+        buf.set_tag(key, value)?;
+        Ok(buf)
+    }
+
+    pub fn render_reuse(&self, buf: &mut String) {
+        use std::fmt::Write;
+
+        buf.clear();
+        write!(buf, "{self}").expect("writing to a String can't fail");
+    }
+
+    pub fn render_line(&self, ending: LineEnding) -> String {
+        format!("{self}{}", ending.as_str())
+    }
+
+    pub fn write_line(
+        &self,
+        mut w: impl std::io::Write,
+        ending: LineEnding,
+    ) -> std::io::Result<()> {
+        write!(w, "{self}{}", ending.as_str())
+    }
+
+    pub fn render_checked(&self) -> Result<String> {
+        if self.command.is_empty() || self.command.contains(' ') {
+            return Err(Error::InvalidCommand);
+        }
+        let line = self.to_string();
+        match line.chars().find(|c| ['\0', '\r', '\n'].contains(c)) {
+            Some(c) => Err(Error::IllegalChar(c)),
+            None => Ok(line),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        let numeric = self.command.len() == 3 && self.command.bytes().all(|b| b.is_ascii_digit());
+        if !numeric
+            && (self.command.is_empty() || !self.command.bytes().all(|b| b.is_ascii_alphabetic()))
+        {
+            return Err(Error::InvalidCommand);
+        }
+
+        let middle = match self.parameters.split_last() {
+            Some((_, middle)) => middle,
+            None => &[],
+        };
+        if middle.iter().any(|p| p.contains(' ')) {
+            return Err(Error::IllegalChar(' '));
+        }
+
+        let line = self.to_string();
+        if let Some(c) = line.chars().find(|c| ['\0', '\r', '\n'].contains(c)) {
+            return Err(Error::IllegalChar(c));
+        }
+
+        let tags = match self.tags.is_empty() {
+            true => 0,
+            false => line.find(' ').map_or(line.len(), |i| i + 1),
+        };
+        if tags > proto::tags::CLIENT_MAX {
+            return Err(Error::TagsTooLong(tags));
+        }
+        match line.len() - tags + LineEnding::Crlf.as_str().len() {
+            len if len > MAX_LINE_LEN => Err(Error::LineTooLong(len)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn spans(&self) -> Option<MessageSpans> {
+        if self.raw.is_empty() {
+            return None;
+        }
+
+        let base = self.raw.as_ptr() as usize;
+        let span = |part: &str| {
+            let start = (part.as_ptr() as usize).checked_sub(base)?;
+            let end = start + part.len();
+            (end <= self.raw.len()).then_some(start..end)
+        };
+
+        let (middle, trailing) = match self.trailing {
+            true => self
+                .parameters
+                .split_last()
+                .map_or((&[][..], None), |(last, rest)| (rest, Some(last))),
+            false => (self.parameters.as_slice(), None),
+        };
+
+        let line = self.raw.strip_prefix('\u{feff}').unwrap_or(self.raw);
+        let bom = self.raw.len() - line.len();
+        Some(MessageSpans {
+            tags: match line.starts_with('@') {
+                true => Some(bom..bom + line.find(' ').unwrap_or(line.len())),
+                false => None,
+            },
+            source: match self.source {
+                Some(source) => Some(span(source)?.start - 1..span(source)?.end),
+                None => None,
+            },
+            // a line that's only tags has nothing to point at but its end
+            command: match self.command {
+                "" => self.raw.len()..self.raw.len(),
+                command => span(command)?,
+            },
+            middle: match middle {
+                [first, .., last] => Some(span(first)?.start..span(last)?.end),
+                [only] => Some(span(only)?),
+                [] => None,
+            },
+            // lenient parsing allows a trailing without its colon
+            trailing: match trailing {
+                Some(last) => {
+                    let last = span(last)?;
+                    match self.raw[..last.start].ends_with(':') {
+                        true => Some(last.start - 1..last.end),
+                        false => Some(last),
+                    }
+                }
+                None => None,
+            },
+        })
+    }
+
+    // `pattern` is a line itself, where a `*` parameter matches any one parameter
+    pub fn matches(&self, pattern: &str) -> bool {
+        let pattern = Message::from(pattern);
+        pattern.command.eq_ignore_ascii_case(self.command)
+            && pattern.parameters.len() == self.parameters.len()
+            && (pattern.parameters.iter())
+                .zip(&self.parameters)
+                .all(|(p, v)| *p == "*" || p == v)
+    }
+
+    // not the wire format, just something that's safe to paste into logs
+    pub fn to_debug_string(&self) -> String {
+        fn quote(out: &mut String, part: &str) {
+            for c in part.chars() {
+                match c {
+                    '"' | '\\' => out.extend(['\\', c]),
+                    c if c.is_ascii_control() => out.push_str(&format!("\\x{:02x}", c as u8)),
+                    // C1 controls like CSI are just as bad for terminals
+                    c if c.is_control() => out.push_str(&format!("\\u{{{:04x}}}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+        }
+
+        let mut out = String::new();
+        for (i, (k, v)) in self.tags.iter().enumerate() {
+            out.push(if i == 0 { '@' } else { ';' });
+            quote(&mut out, k);
+            if !v.is_empty() {
+                out.push('=');
+                quote(&mut out, v);
+            }
+        }
+        if !self.tags.is_empty() {
+            out.push(' ');
+        }
+        if let Some(source) = self.source {
+            out.push(':');
+            quote(&mut out, source);
+            out.push(' ');
+        }
+        quote(&mut out, self.command);
+        for param in &self.parameters {
+            out.push_str(" \"");
+            quote(&mut out, param);
+            out.push('"');
+        }
+        out
+    }
+
+    pub fn needs_pong(&self) -> Option<Command<'_>> {
+        match (self.command, self.parameters.as_slice()) {
+            ("PING", [token]) => Some(Command::Pong {
+                server: None,
+                token,
+            }),
+            ("PING", [token, target]) => Some(Command::Pong {
+                server: Some(target),
+                token,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn numeric(&self) -> Option<u16> {
+        proto::numeric::parse(self.command)
+    }
+
+    pub fn numeric_target(&self) -> Option<&str> {
+        self.numeric()?;
+        self.parameters.first().copied()
+    }
+
+    // may not be the nick that was asked for, if the server truncated or changed it
+    pub fn welcome_nick(&self) -> Option<&str> {
+        match self.numeric()? {
+            1 => self.parameters.first().copied(),
+            _ => None,
+        }
+    }
+
+    pub fn param_after(&self, keyword: &str) -> Option<&str> {
+        let i = self.parameters.iter().position(|p| *p == keyword)?;
+        self.parameters.get(i + 1).copied()
+    }
+
+    pub fn mode_changes(
+        &self,
+        chanmodes: &proto::mode::ChanModes,
+    ) -> Option<Vec<proto::mode::ModeChange<'_>>> {
+        let ("MODE", [_, modes, args @ ..]) = (self.command, self.parameters.as_slice()) else {
+            return None;
+        };
+        let mut changes = proto::mode::parse(modes, args, chanmodes);
+        changes.iter_mut().for_each(|c| c.source = self.source);
+        Some(changes)
+    }
+
+    pub fn auth_notice(&self) -> Option<&str> {
+        match (self.command, self.parameters.as_slice()) {
+            ("NOTICE", ["AUTH" | "*", text]) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn is_auth_notice(&self) -> bool {
+        self.auth_notice().is_some()
+    }
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Crlf => "\r\n",
+            Self::Lf => "\n",
+        }
+    }
+}
+
+impl MessageBuf {
+    pub fn as_message(&self) -> Message<'_> {
+        Message {
+            tags: (self.tags.iter())
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect(),
+            source: self.source.as_deref(),
+            command: &self.command,
+            parameters: self.parameters.iter().map(String::as_str).collect(),
+            trailing: self.trailing,
+            raw: "",
+        }
+    }
+
+    pub fn set_tag(&mut self, key: &str, value: &str) -> Result<()> {
+        proto::tags::check_key(key)?;
+        let value = proto::tags::escape(value).into_owned();
+        match self.tags.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value,
+            None => self.tags.push((key.into(), value)),
+        }
+        Ok(())
+    }
+}
+
+impl OwnedMessage {
+    pub fn message(&self) -> Message<'_> {
+        Message::from(self.line.as_str())
+    }
+
+    pub fn tag(&self, key: &str) -> Option<Cow<'_, str>> {
+        proto::tags::TagIter::from_line(&self.line).get(key)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.line
+    }
+
+    pub fn into_string(self) -> String {
+        self.line
+    }
+}
+
+impl From<String> for OwnedMessage {
+    fn from(mut line: String) -> Self {
+        line.truncate(line.trim_end_matches(['\r', '\n']).len());
+        Self { line }
+    }
+}
+
+impl From<&Message<'_>> for MessageBuf {
+    fn from(msg: &Message<'_>) -> Self {
+        Self {
+            tags: (msg.tags.iter())
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            source: msg.source.map(Into::into),
+            command: msg.command.into(),
+            parameters: msg.parameters.iter().map(|p| p.to_string()).collect(),
+            trailing: msg.trailing,
+        }
+    }
+}
+
+// raw is just where the message came from, two messages saying the same thing are equal
+impl PartialEq for Message<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.tags == other.tags
+            && self.source == other.source
+            && self.command == other.command
+            && self.parameters == other.parameters
+            && self.trailing == other.trailing
+    }
+}
+
+impl Eq for Message<'_> {}
+
+impl std::fmt::Display for MessageBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_message().fmt(f)
+    }
 }
 
 impl std::fmt::Display for Message<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (k, v)) in self.tags.iter().enumerate() {
+            write!(f, "{}{k}", if i == 0 { '@' } else { ';' })?;
+            if !v.is_empty() {
+                write!(f, "={v}")?;
+            }
+        }
+        if !self.tags.is_empty() {
+            write!(f, " ")?;
+        }
+
         if let Some(src) = self.source {
             write!(f, ":{src} ")?;
         }
@@ -21,7 +458,10 @@ impl std::fmt::Display for Message<'_> {
 
         if let Some((last, rest)) = self.parameters.split_last() {
             rest.iter().try_for_each(|v| write!(f, " {v}"))?;
-            write!(f, " :{last}")?;
+            match self.trailing || last.is_empty() || last.contains(' ') || last.starts_with(':') {
+                true => write!(f, " :{last}")?,
+                false => write!(f, " {last}")?,
+            }
         }
 
         Ok(())
@@ -30,11 +470,15 @@ impl std::fmt::Display for Message<'_> {
 
 struct Lexer<'a> {
     input: &'a str,
+    middle: fn(&str) -> Option<usize>,
 }
 
 impl<'a> Lexer<'a> {
     fn new(input: &'a str) -> Self {
-        Self { input }
+        Self {
+            input,
+            middle: |_| None,
+        }
     }
 
     fn current(&self) -> Option<char> {
@@ -51,6 +495,18 @@ impl<'a> Lexer<'a> {
     }
 
     fn parse(&mut self) -> Message<'a> {
+        let raw = self.input;
+        let tags = match self.current() {
+            Some('@') => {
+                self.input = &self.input[1..];
+                (self.read_part().split(';'))
+                    .filter(|tag| !tag.is_empty())
+                    .map(|tag| tag.split_once('=').unwrap_or((tag, "")))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
         let source = match self.current() {
             Some(':') => {
                 self.input = &self.input[1..];
@@ -60,27 +516,44 @@ impl<'a> Lexer<'a> {
         };
 
         let command = self.read_part();
+        let middle = (self.middle)(command);
 
         let mut parameters = Vec::new();
-        loop {
+        let trailing = loop {
             match self.current() {
-                Some(':') => break parameters.push(&self.input[1..]),
+                Some(_) if middle == Some(parameters.len()) => {
+                    parameters.push(self.input.strip_prefix(':').unwrap_or(self.input));
+                    break true;
+                }
+                Some(':') => {
+                    parameters.push(&self.input[1..]);
+                    break true;
+                }
                 Some(_) => parameters.push(self.read_part()),
-                None => break,
+                None => break false,
             }
-        }
+        };
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(command, parameters = parameters.len(), "parsed message");
 
         Message {
+            tags,
             source,
             command,
             parameters,
+            trailing,
+            raw,
         }
     }
 }
 
 impl<'a> From<&'a str> for Message<'a> {
     fn from(value: &'a str) -> Self {
-        Lexer::new(value).parse()
+        Message {
+            raw: value,
+            ..Lexer::new(value.strip_prefix('\u{feff}').unwrap_or(value)).parse()
+        }
     }
 }
 
@@ -97,22 +570,514 @@ mod tests {
         };
     }
 
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn trace_parse() {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::{field, span, Event, Metadata};
+
+        #[derive(Default)]
+        struct Commands(Arc<Mutex<Vec<String>>>);
+
+        impl field::Visit for Commands {
+            fn record_str(&mut self, field: &field::Field, value: &str) {
+                if field.name() == "command" {
+                    self.0.lock().unwrap().push(value.into());
+                }
+            }
+
+            fn record_debug(&mut self, _: &field::Field, _: &dyn std::fmt::Debug) {}
+        }
+
+        impl tracing::Subscriber for Commands {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+            fn event(&self, event: &Event<'_>) {
+                event.record(&mut Commands(self.0.clone()));
+            }
+
+            fn enter(&self, _: &span::Id) {}
+
+            fn exit(&self, _: &span::Id) {}
+        }
+
+        let commands = Commands::default();
+        let seen = commands.0.clone();
+        tracing::subscriber::with_default(commands, || {
+            let _ = Message::from(":irc.example.com CAP * LS :sasl");
+            let _ = Message::from("PING :irc.example.com");
+
+            let ser = (Serializer::default().argument("NOTICE"))
+                .and_then(|ser| ser.argument("rini"))
+                .and_then(|ser| ser.argument("hi"))
+                .unwrap();
+            let _ = ser.to_message();
+        });
+
+        assert_eq!(*seen.lock().unwrap(), ["CAP", "PING", "NOTICE"]);
+    }
+
+    test_parse! {
+        parse_tags;
+        "@id=123AB;rose :nick!user@host PRIVMSG #irk :hi" => Ok(Message {
+            tags: vec![("id", "123AB"), ("rose", "")],
+            source: Some("nick!user@host"),
+            command: "PRIVMSG",
+            parameters: vec!["#irk", "hi"],
+            trailing: true,
+            ..Default::default()
+        }),
+        "@+example=raw\\:value;;empty= TAGMSG #irk" => Ok(Message {
+            tags: vec![("+example", "raw\\:value"), ("empty", "")],
+            source: None,
+            command: "TAGMSG",
+            parameters: vec!["#irk"],
+            trailing: false,
+            ..Default::default()
+        })
+    }
+
     test_parse! {
         parse_rfc;
         ":irc.example.com CAP * LIST :" => Ok(Message {
+            tags: vec![],
             source: Some("irc.example.com"),
             command: "CAP",
             parameters: vec!["*", "LIST", ""],
+            trailing: true,
+            ..Default::default()
         }),
         "CAP * LS :multi-prefix sasl" => Ok(Message {
+            tags: vec![],
             source: None,
             command: "CAP",
             parameters: vec!["*", "LS", "multi-prefix sasl"],
+            trailing: true,
+            ..Default::default()
         }),
         "CAP REQ :sasl message-tags foo" => Ok(Message {
+            tags: vec![],
             source: None,
             command: "CAP",
             parameters: vec!["REQ", "sasl message-tags foo"],
+            trailing: true,
+            ..Default::default()
         })
     }
+
+    #[test]
+    fn numeric_target() {
+        let msg = Message::from(":irc.example.com 001 rini :Welcome to IRC");
+        assert_eq!(msg.numeric_target(), Some("rini"));
+
+        let msg = Message::from(":irc.example.com 451 * :You have not registered");
+        assert_eq!(msg.numeric_target(), Some("*"));
+
+        let msg = Message::from(":rini!rini@example.com PRIVMSG #irk :hi");
+        assert_eq!(msg.numeric_target(), None);
+    }
+
+    #[test]
+    fn render_tagmsg() {
+        use serde::Deserialize;
+
+        let line = "@+typing=active TAGMSG #irk";
+        assert_eq!(Message::from(line).to_string(), line);
+
+        let ser = Serializer::default()
+            .compact()
+            .argument(Command::Tagmsg { target: "#irk" })
+            .unwrap();
+        let buf = ser
+            .to_message()
+            .unwrap()
+            .with_tag("+typing", "active")
+            .unwrap();
+        assert_eq!(buf.to_string(), line);
+
+        let msg = Message::from(line);
+        assert_eq!(
+            Command::deserialize(&mut proto::Deserializer::from_message(msg)),
+            Ok(Command::Tagmsg { target: "#irk" })
+        );
+    }
+
+    #[test]
+    fn welcome_nick() {
+        let msg = Message::from(":irc.example.com 001 rini_ :Welcome to IRC, rini_");
+        assert_eq!(msg.welcome_nick(), Some("rini_"));
+
+        let msg = Message::from(":irc.example.com 002 rini_ :Your host is irc.example.com");
+        assert_eq!(msg.welcome_nick(), None);
+    }
+
+    #[test]
+    fn auth_notice() {
+        let msg = Message::from(":irc.example.com NOTICE AUTH :*** Looking up your hostname...");
+        assert!(msg.is_auth_notice());
+        assert_eq!(msg.auth_notice(), Some("*** Looking up your hostname..."));
+
+        let msg = Message::from(":irc.example.com NOTICE * :*** Found your hostname");
+        assert_eq!(msg.auth_notice(), Some("*** Found your hostname"));
+
+        let msg = Message::from(":alice!alice@example.com NOTICE rini :hi there");
+        assert!(!msg.is_auth_notice());
+    }
+
+    #[test]
+    fn param_after() {
+        let msg = Message::from("MODE #irk +b *!*@spam.example.com");
+        assert_eq!(msg.param_after("+b"), Some("*!*@spam.example.com"));
+        assert_eq!(msg.param_after("*!*@spam.example.com"), None);
+        assert_eq!(msg.param_after("+o"), None);
+    }
+
+    #[test]
+    fn with_tag() {
+        let msg = Message::from(":rini!rini@example.com PRIVMSG #irk :hi");
+        let buf = msg.with_tag("time", "2023-11-14T22:13:20.000Z").unwrap();
+        assert_eq!(
+            buf.to_string(),
+            "@time=2023-11-14T22:13:20.000Z :rini!rini@example.com PRIVMSG #irk :hi"
+        );
+
+        let msg = Message::from("@time=old;account=rini PRIVMSG #irk :hi");
+        let buf = msg.with_tag("time", "new; improved").unwrap();
+        assert_eq!(
+            buf.as_message().tag("time").as_deref(),
+            Some("new; improved")
+        );
+        assert_eq!(
+            buf.to_string(),
+            "@time=new\\:\\simproved;account=rini PRIVMSG #irk :hi"
+        );
+
+        assert_eq!(msg.with_tag("a b", "c"), Err(Error::InvalidTagKey));
+        assert_eq!(msg.with_tag("", "c"), Err(Error::InvalidTagKey));
+    }
+
+    #[test]
+    fn owned_message() {
+        fn read_line() -> OwnedMessage {
+            let line = String::from(":rini!rini@example.com PRIVMSG #irk :hello world\r\n");
+            OwnedMessage::from(line)
+        }
+
+        let msgs = [read_line(), read_line()];
+        let msg = msgs[1].message();
+        assert_eq!(msg.source, Some("rini!rini@example.com"));
+        assert_eq!(msg.command, "PRIVMSG");
+        assert_eq!(msg.parameters, ["#irk", "hello world"]);
+    }
+
+    #[test]
+    fn render_reuse() {
+        let mut buf = String::new();
+
+        Message::from("PRIVMSG #irk :a rather long message body").render_reuse(&mut buf);
+        let capacity = buf.capacity();
+
+        Message::from("PING :irc.example.com").render_reuse(&mut buf);
+        assert_eq!(buf, "PING :irc.example.com");
+        assert_eq!(buf.capacity(), capacity);
+    }
+
+    #[test]
+    fn nested() {
+        let msg = Message::from(":relay.example.com RELAY #irk :PRIVMSG #irk :hi there");
+        assert_eq!(
+            msg.nested(),
+            Ok(Message {
+                command: "PRIVMSG",
+                parameters: vec!["#irk", "hi there"],
+                trailing: true,
+                ..Default::default()
+            })
+        );
+
+        let msg =
+            Message::from(":relay.example.com RELAY #irk ::alice!a@example.com PRIVMSG #irk :hi");
+        assert_eq!(
+            msg.nested(),
+            Ok(Message {
+                source: Some("alice!a@example.com"),
+                command: "PRIVMSG",
+                parameters: vec!["#irk", "hi"],
+                trailing: true,
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(Message::from("ENCAP * :").nested(), Err(Error::Eof));
+        assert_eq!(Message::from("ENCAP").nested(), Err(Error::Eof));
+    }
+
+    #[test]
+    fn strip_tags() {
+        let msg = Message::from("@msgid=abc;+typing=active :rini!r@h PRIVMSG #irk :hi there");
+        let stripped = msg.strip_tags();
+        assert!(stripped.tags.is_empty());
+        assert_eq!(stripped.to_string(), ":rini!r@h PRIVMSG #irk :hi there");
+        assert_eq!(stripped.parameters, msg.parameters);
+    }
+
+    #[test]
+    fn line_endings() {
+        let msg = Message::from("PRIVMSG #irk :hi");
+        assert_eq!(
+            msg.render_line(LineEnding::default()),
+            "PRIVMSG #irk :hi\r\n"
+        );
+        assert_eq!(msg.render_line(LineEnding::Lf), "PRIVMSG #irk :hi\n");
+
+        let mut out = Vec::new();
+        msg.write_line(&mut out, LineEnding::Crlf).unwrap();
+        msg.write_line(&mut out, LineEnding::Lf).unwrap();
+        assert_eq!(out, b"PRIVMSG #irk :hi\r\nPRIVMSG #irk :hi\n");
+    }
+
+    #[test]
+    fn bom() {
+        let line = "\u{feff}NICK rini";
+        assert_eq!(Message::from(line), Message::from("NICK rini"));
+        assert_eq!(
+            Message::parse_strict(line),
+            Err(Error::IllegalChar('\u{feff}'))
+        );
+
+        assert_eq!(
+            Message::parse_strict("NICK rini"),
+            Ok(Message::from("NICK rini"))
+        );
+        assert_eq!(
+            Message::parse_strict("NICK ri\0ni"),
+            Err(Error::IllegalChar('\0'))
+        );
+    }
+
+    #[test]
+    fn render_checked() {
+        let msg = Message {
+            command: "",
+            parameters: vec!["#irk", "hi"],
+            ..Default::default()
+        };
+        assert_eq!(msg.render_checked(), Err(Error::InvalidCommand));
+
+        let msg = Message::from("PRIVMSG #irk :hi");
+        assert_eq!(msg.render_checked().as_deref(), Ok("PRIVMSG #irk :hi"));
+
+        let msg = Message {
+            command: "PRIVMSG",
+            parameters: vec!["#irk", "hi\r\nQUIT"],
+            ..Default::default()
+        };
+        assert_eq!(msg.render_checked(), Err(Error::IllegalChar('\r')));
+    }
+
+    #[test]
+    fn validate() {
+        let msg = Message::from("@+draft/reply=1 :alice!a@h PRIVMSG #irk :hi there");
+        assert_eq!(msg.validate(), Ok(()));
+        assert_eq!(
+            Message::from(":irc.example.com 001 rini :hi").validate(),
+            Ok(())
+        );
+
+        let msg = |command, parameters| Message {
+            command,
+            parameters,
+            ..Default::default()
+        };
+        assert_eq!(msg("", vec!["x"]).validate(), Err(Error::InvalidCommand));
+        assert_eq!(
+            msg("PRIV-MSG", vec!["x"]).validate(),
+            Err(Error::InvalidCommand)
+        );
+        assert_eq!(msg("01", vec!["x"]).validate(), Err(Error::InvalidCommand));
+        assert_eq!(
+            msg("KICK", vec!["#irk", "alice bob", "bye"]).validate(),
+            Err(Error::IllegalChar(' '))
+        );
+        assert_eq!(
+            msg("PRIVMSG", vec!["#irk", "hi\nQUIT"]).validate(),
+            Err(Error::IllegalChar('\n'))
+        );
+        assert_eq!(
+            msg("NICK", vec!["ri\0ni"]).validate(),
+            Err(Error::IllegalChar('\0'))
+        );
+
+        let text = "a".repeat(500);
+        assert_eq!(
+            msg("PRIVMSG", vec!["#irk", &text]).validate(),
+            Err(Error::LineTooLong(515))
+        );
+
+        let value = "v".repeat(5000);
+        let msg = Message {
+            tags: vec![("+x", &value)],
+            ..msg("PING", vec!["tok"])
+        };
+        assert_eq!(msg.validate(), Err(Error::TagsTooLong(5005)));
+    }
+
+    #[test]
+    fn spans() {
+        let line = "@id=1;+x :alice!a@h PRIVMSG #irk extra :hi there";
+        let spans = Message::from(line).spans().unwrap();
+        assert_eq!(
+            spans,
+            MessageSpans {
+                tags: Some(0..8),
+                source: Some(9..19),
+                command: 20..27,
+                middle: Some(28..38),
+                trailing: Some(39..48),
+            }
+        );
+        assert_eq!(&line[spans.source.unwrap()], ":alice!a@h");
+        assert_eq!(&line[spans.middle.unwrap()], "#irk extra");
+        assert_eq!(&line[spans.trailing.unwrap()], ":hi there");
+
+        let spans = Message::from("PING").spans().unwrap();
+        assert_eq!(
+            spans,
+            MessageSpans {
+                command: 0..4,
+                ..Default::default()
+            }
+        );
+
+        let line = "PRIVMSG #a hello there";
+        let spans = Message::parse_lenient(line, |_| Some(1)).spans().unwrap();
+        assert_eq!(spans.trailing, Some(11..22));
+        assert_eq!(&line[spans.trailing.unwrap()], "hello there");
+
+        let spans = Message::from("\u{feff}NICK rini").spans().unwrap();
+        assert_eq!((spans.tags, spans.command), (None, 3..7));
+
+        let line = "\u{feff}@a=b NICK rini";
+        let spans = Message::from(line).spans().unwrap();
+        assert_eq!(&line[spans.tags.unwrap()], "@a=b");
+        assert_eq!(&line[spans.command], "NICK");
+        assert_eq!(&line[spans.middle.unwrap()], "rini");
+
+        let spans = Message::from("@a=b").spans().unwrap();
+        assert_eq!(
+            spans,
+            MessageSpans {
+                tags: Some(0..4),
+                command: 4..4,
+                ..Default::default()
+            }
+        );
+
+        let msg = Message {
+            command: "PING",
+            ..Default::default()
+        };
+        assert_eq!(msg.spans(), None);
+    }
+
+    #[test]
+    fn from_parts() {
+        let msg = Message::from_parts(Some("rini!r@h"), "PRIVMSG", &["#irk", "hi there"]);
+        assert!(msg.trailing);
+        assert_eq!(msg.to_string(), ":rini!r@h PRIVMSG #irk :hi there");
+
+        let msg = Message::from_parts(None, "JOIN", &["#irk"]);
+        assert!(!msg.trailing);
+        assert_eq!(msg.to_string(), "JOIN #irk");
+
+        assert_eq!(
+            Message::from_parts(None, "AWAY", &[""]).to_string(),
+            "AWAY :"
+        );
+    }
+
+    #[test]
+    fn colonless_trailing() {
+        fn middle(command: &str) -> Option<usize> {
+            match command {
+                "PRIVMSG" | "NOTICE" => Some(1),
+                _ => None,
+            }
+        }
+
+        let line = ":alice!a@h PRIVMSG #irk hello there  friends";
+        assert_eq!(
+            Message::from(line).parameters,
+            ["#irk", "hello", "there", "friends"]
+        );
+
+        let msg = Message::parse_lenient(line, middle);
+        assert_eq!(msg.parameters, ["#irk", "hello there  friends"]);
+        assert!(msg.trailing);
+
+        let msg = Message::parse_lenient("PRIVMSG #irk :hi there", middle);
+        assert_eq!(msg.parameters, ["#irk", "hi there"]);
+        let msg = Message::parse_lenient("KICK #irk alice being rude", middle);
+        assert_eq!(msg.parameters, ["#irk", "alice", "being", "rude"]);
+    }
+
+    #[test]
+    fn matches() {
+        let msg = Message::from(":alice!a@h PRIVMSG #irk :hello there");
+        assert!(msg.matches("PRIVMSG #irk *"));
+        assert!(msg.matches("privmsg * :hello there"));
+        assert!(!msg.matches("PRIVMSG #rust *"));
+        assert!(!msg.matches("NOTICE #irk *"));
+        assert!(!msg.matches("PRIVMSG *"));
+    }
+
+    #[test]
+    fn debug_string() {
+        let msg = Message::from(":alice!a@h PRIVMSG #irk :\x01ACTION says \"hi\"\x01");
+        assert_eq!(
+            msg.to_debug_string(),
+            r##":alice!a@h PRIVMSG "#irk" "\x01ACTION says \"hi\"\x01""##
+        );
+
+        let msg = Message::from("PRIVMSG #irk :\u{9b}31mred\u{85}");
+        assert_eq!(
+            msg.to_debug_string(),
+            r##"PRIVMSG "#irk" "\u{009b}31mred\u{0085}""##
+        );
+    }
+
+    #[test]
+    fn needs_pong() {
+        let msg = Message::from("PING :irc.example.com");
+        assert_eq!(
+            msg.needs_pong(),
+            Some(Command::Pong {
+                server: None,
+                token: "irc.example.com",
+            })
+        );
+
+        let msg = Message::from("PING serverA serverB");
+        assert_eq!(
+            msg.needs_pong(),
+            Some(Command::Pong {
+                server: Some("serverB"),
+                token: "serverA",
+            })
+        );
+
+        assert_eq!(Message::from("PONG :irc.example.com").needs_pong(), None);
+        assert_eq!(Message::from("PING").needs_pong(), None);
+    }
 }
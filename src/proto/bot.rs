@@ -0,0 +1,22 @@
+pub fn parse_command(text: &str, prefix: char) -> Option<(&str, Vec<&str>)> {
+    let mut words = text.strip_prefix(prefix)?.split(' ');
+    let command = words.next().filter(|c| !c.is_empty())?;
+    Some((command, words.filter(|w| !w.is_empty()).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("!help", '!'), Some(("help", vec![])));
+        assert_eq!(
+            parse_command("!kick alice  being rude", '!'),
+            Some(("kick", vec!["alice", "being", "rude"]))
+        );
+        assert_eq!(parse_command("help me", '!'), None);
+        assert_eq!(parse_command("! help", '!'), None);
+        assert_eq!(parse_command("", '!'), None);
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+
+use super::{Cap, Command};
+use crate::Message;
+
+pub fn parse(list: &str) -> impl Iterator<Item = (&str, Option<&str>)> {
+    list.split(' ')
+        .filter(|cap| !cap.is_empty())
+        .map(|cap| match cap.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (cap, None),
+        })
+}
+
+// for values that are lists themselves, like `sasl=PLAIN,EXTERNAL`
+pub fn values(value: &str) -> Vec<&str> {
+    value.split(',').filter(|v| !v.is_empty()).collect()
+}
+
+pub fn vendor(name: &str) -> Option<&str> {
+    name.split_once('/').map(|(vendor, _)| vendor)
+}
+
+pub fn is_draft(name: &str) -> bool {
+    vendor(name) == Some("draft")
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ListLine<'a> {
+    More(&'a str),
+    Last(&'a str),
+}
+
+// `CAP * LS * :...` means more lines are coming, the final one has no `*` before the list
+pub fn list_line<'a>(msg: &Message<'a>) -> Option<ListLine<'a>> {
+    match (msg.command, msg.parameters.as_slice()) {
+        ("CAP", [_, "LS" | "LIST", "*", caps]) => Some(ListLine::More(caps)),
+        ("CAP", [_, "LS" | "LIST", caps]) => Some(ListLine::Last(caps)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct CapSet {
+    caps: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Diff<'a> {
+    pub added: Vec<&'a str>,
+    pub removed: Vec<&'a str>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Change {
+    Added(Vec<String>),
+    Removed(Vec<String>),
+}
+
+impl CapSet {
+    // for `CAP NEW` and `CAP DEL` arriving mid-session
+    pub fn apply(&mut self, msg: &Message) -> Option<Change> {
+        let names = |list| parse(list).map(|(name, _)| name.to_string());
+        match (msg.command, msg.parameters.as_slice()) {
+            ("CAP", [_, "NEW", list]) => {
+                self.apply_new(list);
+                Some(Change::Added(names(list).collect()))
+            }
+            ("CAP", [_, "DEL", list]) => {
+                let removed = names(list)
+                    .filter(|name| self.caps.contains_key(name))
+                    .collect();
+                self.apply_del(list);
+                Some(Change::Removed(removed))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<Option<&str>> {
+        self.caps.get(name).map(Option::as_deref)
+    }
+
+    // for caps that change how other commands behave, like `draft/no-implicit-names` on JOIN
+    pub fn has(&self, name: &str) -> bool {
+        self.caps.contains_key(name)
+    }
+
+    pub fn values(&self, name: &str) -> Option<Vec<&str>> {
+        self.get(name)
+            .map(|value| values(value.unwrap_or_default()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.caps.iter().map(|(k, v)| (k.as_str(), v.as_deref()))
+    }
+
+    pub fn apply_new(&mut self, list: &str) {
+        for (name, value) in parse(list) {
+            self.caps.insert(name.into(), value.map(Into::into));
+        }
+    }
+
+    pub fn apply_del(&mut self, list: &str) {
+        for (name, _) in parse(list) {
+            self.caps.remove(name);
+        }
+    }
+
+    // `-cap` in an ACK means it got turned off
+    fn apply_ack(&mut self, list: &str) {
+        for (name, value) in parse(list) {
+            match name.strip_prefix('-') {
+                Some(name) => self.caps.remove(name),
+                None => self.caps.insert(name.into(), value.map(Into::into)),
+            };
+        }
+    }
+
+    pub fn diff<'a>(&'a self, other: &'a CapSet) -> Diff<'a> {
+        Diff {
+            added: other
+                .iter()
+                .filter(|(name, value)| self.get(name) != Some(*value))
+                .map(|(name, _)| name)
+                .collect(),
+            removed: self
+                .iter()
+                .filter(|(name, _)| !other.caps.contains_key(*name))
+                .map(|(name, _)| name)
+                .collect(),
+        }
+    }
+}
+
+pub fn finish() -> Command<'static> {
+    Command::Cap(Cap::End)
+}
+
+// requests whichever of `wanted` the server lists, then ENDs once every REQ got its ACK/NAK
+#[derive(Debug, Default)]
+pub struct Negotiator {
+    wanted: Vec<String>,
+    available: CapSet,
+    enabled: CapSet,
+    requested: String,
+    pending: usize,
+    done: bool,
+}
+
+impl Negotiator {
+    pub fn new<S: Into<String>>(wanted: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            wanted: wanted.into_iter().map(Into::into).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn available(&self) -> &CapSet {
+        &self.available
+    }
+
+    pub fn enabled(&self) -> &CapSet {
+        &self.enabled
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn feed(&mut self, msg: &Message) -> Option<Command<'_>> {
+        if self.done {
+            return None;
+        }
+
+        match list_line(msg) {
+            Some(ListLine::More(caps)) => {
+                self.available.apply_new(caps);
+                return None;
+            }
+            Some(ListLine::Last(caps)) => {
+                self.available.apply_new(caps);
+                self.requested = (self.wanted.iter())
+                    .filter(|cap| self.available.get(cap).is_some())
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if !self.requested.is_empty() {
+                    self.pending += 1;
+                    return Some(Command::Cap(Cap::Req {
+                        caps: &self.requested,
+                    }));
+                }
+            }
+            None => match (msg.command, msg.parameters.as_slice()) {
+                ("CAP", [_, "ACK", list]) => {
+                    self.enabled.apply_ack(list);
+                    self.pending = self.pending.saturating_sub(1);
+                }
+                ("CAP", [_, "NAK", ..]) => self.pending = self.pending.saturating_sub(1),
+                _ => return None,
+            },
+        }
+
+        if self.pending > 0 {
+            return None;
+        }
+        self.done = true;
+        Some(finish())
+    }
+}
+
+impl From<&str> for CapSet {
+    fn from(list: &str) -> Self {
+        let mut set = Self::default();
+        set.apply_new(list);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespaces() {
+        assert!(is_draft("draft/chathistory"));
+        assert_eq!(vendor("draft/chathistory"), Some("draft"));
+
+        assert!(!is_draft("znc.in/playback"));
+        assert_eq!(vendor("znc.in/playback"), Some("znc.in"));
+
+        assert!(!is_draft("message-tags"));
+        assert_eq!(vendor("message-tags"), None);
+    }
+
+    #[test]
+    fn new_then_del() {
+        let mut caps = CapSet::from("multi-prefix sasl=PLAIN");
+        caps.apply_new("away-notify sasl=PLAIN,EXTERNAL");
+        caps.apply_del("multi-prefix");
+
+        assert_eq!(caps, CapSet::from("away-notify sasl=PLAIN,EXTERNAL"));
+        assert_eq!(caps.get("sasl"), Some(Some("PLAIN,EXTERNAL")));
+        assert_eq!(caps.get("multi-prefix"), None);
+    }
+
+    #[test]
+    fn list_values() {
+        let (name, value) = parse("sasl=PLAIN,EXTERNAL,SCRAM-SHA-256").next().unwrap();
+        assert_eq!(name, "sasl");
+        assert_eq!(
+            values(value.unwrap()),
+            ["PLAIN", "EXTERNAL", "SCRAM-SHA-256"]
+        );
+
+        let caps = CapSet::from("sasl=PLAIN,EXTERNAL,SCRAM-SHA-256 away-notify");
+        assert_eq!(
+            caps.values("sasl"),
+            Some(vec!["PLAIN", "EXTERNAL", "SCRAM-SHA-256"])
+        );
+        assert_eq!(caps.values("away-notify"), Some(vec![]));
+        assert_eq!(caps.values("batch"), None);
+    }
+
+    #[test]
+    fn diff() {
+        let old = CapSet::from("multi-prefix sasl=PLAIN");
+        let new = CapSet::from("away-notify sasl=PLAIN,EXTERNAL");
+
+        assert_eq!(
+            old.diff(&new),
+            Diff {
+                added: vec!["away-notify", "sasl"],
+                removed: vec!["multi-prefix"],
+            }
+        );
+        assert_eq!(new.diff(&new), Diff::default());
+    }
+
+    #[test]
+    fn list_continuation() {
+        let msg = Message::from(":irc.example.com CAP * LS * :multi-prefix sasl=PLAIN");
+        assert_eq!(
+            list_line(&msg),
+            Some(ListLine::More("multi-prefix sasl=PLAIN"))
+        );
+
+        let msg = Message::from(":irc.example.com CAP * LS :away-notify");
+        assert_eq!(list_line(&msg), Some(ListLine::Last("away-notify")));
+
+        let msg = Message::from(":irc.example.com CAP rini LIST :sasl");
+        assert_eq!(list_line(&msg), Some(ListLine::Last("sasl")));
+
+        let msg = Message::from(":irc.example.com CAP rini ACK :sasl");
+        assert_eq!(list_line(&msg), None);
+    }
+
+    #[test]
+    fn negotiator() {
+        let mut neg = Negotiator::new(["sasl", "away-notify", "batch"]);
+
+        let ls = Message::from(":irc.example.com CAP * LS * :multi-prefix sasl=PLAIN");
+        assert_eq!(neg.feed(&ls), None);
+        let ls = Message::from(":irc.example.com CAP * LS :away-notify");
+        assert_eq!(
+            neg.feed(&ls),
+            Some(Command::Cap(Cap::Req {
+                caps: "sasl away-notify"
+            }))
+        );
+
+        assert_eq!(neg.feed(&Message::from("PING :x")), None);
+        assert!(!neg.is_done());
+
+        let ack = Message::from(":irc.example.com CAP * ACK :sasl away-notify");
+        assert_eq!(neg.feed(&ack), Some(finish()));
+        assert!(neg.is_done());
+        assert_eq!(neg.feed(&ack), None);
+    }
+
+    #[test]
+    fn enabled_caps() {
+        let mut neg = Negotiator::new(["draft/no-implicit-names", "batch"]);
+        let ls = Message::from(":irc.example.com CAP * LS :draft/no-implicit-names batch");
+        assert!(neg.feed(&ls).is_some());
+        assert!(neg.available().has("batch"));
+        assert!(!neg.enabled().has("batch"));
+
+        let ack = Message::from(":irc.example.com CAP * ACK :draft/no-implicit-names");
+        assert_eq!(neg.feed(&ack), Some(finish()));
+        assert!(neg.enabled().has("draft/no-implicit-names"));
+        assert!(!neg.enabled().has("batch"));
+    }
+
+    #[test]
+    fn negotiator_nothing_wanted() {
+        let mut neg = Negotiator::new(["batch"]);
+        let ls = Message::from(":irc.example.com CAP * LS :multi-prefix");
+        assert_eq!(neg.feed(&ls), Some(finish()));
+    }
+
+    #[test]
+    fn del_mid_session() {
+        let mut caps = CapSet::from("sasl=PLAIN away-notify");
+
+        let msg = Message::from(":irc.example.com CAP rini DEL :sasl batch");
+        assert_eq!(caps.apply(&msg), Some(Change::Removed(vec!["sasl".into()])));
+        assert_eq!(caps.get("sasl"), None);
+        assert_eq!(caps.get("away-notify"), Some(None));
+
+        let msg = Message::from(":irc.example.com CAP rini NEW :sasl=EXTERNAL");
+        assert_eq!(caps.apply(&msg), Some(Change::Added(vec!["sasl".into()])));
+        assert_eq!(caps.get("sasl"), Some(Some("EXTERNAL")));
+
+        assert_eq!(caps.apply(&Message::from("PING :x")), None);
+    }
+}
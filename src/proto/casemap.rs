@@ -0,0 +1,65 @@
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum CaseMapping {
+    Ascii,
+    #[default]
+    Rfc1459,
+    StrictRfc1459,
+}
+
+impl CaseMapping {
+    pub fn from_isupport(value: &str) -> Option<Self> {
+        match value {
+            "ascii" => Some(Self::Ascii),
+            "rfc1459" => Some(Self::Rfc1459),
+            "strict-rfc1459" => Some(Self::StrictRfc1459),
+            _ => None,
+        }
+    }
+
+    pub fn fold_char(self, c: char) -> char {
+        match (self, c) {
+            (_, 'A'..='Z') => c.to_ascii_lowercase(),
+            (Self::Rfc1459 | Self::StrictRfc1459, '[') => '{',
+            (Self::Rfc1459 | Self::StrictRfc1459, ']') => '}',
+            (Self::Rfc1459 | Self::StrictRfc1459, '\\') => '|',
+            (Self::Rfc1459, '^') => '~',
+            _ => c,
+        }
+    }
+
+    pub fn fold(self, s: &str) -> String {
+        s.chars().map(|c| self.fold_char(c)).collect()
+    }
+}
+
+pub fn normalize_channel(input: &str, casemapping: CaseMapping) -> String {
+    let input = input.trim();
+    match input.starts_with(['#', '&', '!', '+']) {
+        true => casemapping.fold(input),
+        false => casemapping.fold(&format!("#{input}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn channels() {
+        let rfc = CaseMapping::Rfc1459;
+        assert_eq!(normalize_channel("Foo", rfc), "#foo");
+        assert_eq!(normalize_channel("#foo", rfc), "#foo");
+        assert_eq!(normalize_channel(" #FOO ", rfc), "#foo");
+        assert_eq!(normalize_channel("&Local", rfc), "&local");
+        assert_eq!(normalize_channel("#[irk]^", rfc), "#{irk}~");
+        assert_eq!(normalize_channel("#[irk]~", rfc), "#{irk}~");
+        assert_eq!(rfc.fold("#a^"), "#a~");
+
+        let strict = CaseMapping::StrictRfc1459;
+        assert_eq!(normalize_channel("#[irk]^", strict), "#{irk}^");
+
+        let ascii = CaseMapping::from_isupport("ascii").unwrap();
+        assert_eq!(normalize_channel("[Irk]", ascii), "#[irk]");
+        assert_eq!(CaseMapping::from_isupport("rfc7613"), None);
+    }
+}
@@ -1,20 +1,100 @@
-use serde::de::{self, Visitor};
+use std::{borrow::Cow, marker::PhantomData};
+
+use serde::de::{
+    self,
+    value::{BorrowedStrDeserializer, StringDeserializer},
+    Visitor,
+};
 
 use crate::{Error, Result};
 
+const GREEDY: &str = "$irk::Greedy";
+
+// for `#[serde(deserialize_with)]` on variants like `LUSERS [<mask> [<target>]]`, where options
+// fill in left to right instead of only when every field after them has a parameter too
+pub fn greedy<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: de::Deserializer<'de>,
+    T: de::Deserialize<'de>,
+{
+    struct Greedy<T>(PhantomData<T>);
+
+    impl<'de, T: de::Deserialize<'de>> Visitor<'de> for Greedy<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "parameters")
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> std::result::Result<T, D::Error>
+        where
+            D: de::Deserializer<'de>,
+        {
+            T::deserialize(deserializer)
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(GREEDY, Greedy(PhantomData))
+}
+
 pub struct Deserializer<'de> {
     input: (Option<&'de str>, Vec<&'de str>),
+    tags: Vec<(&'de str, &'de str)>,
+    params: usize,
     fields: usize,
+    greedy: bool,
+    names: (&'static [&'static str], usize),
 }
 
 impl<'de> Deserializer<'de> {
     pub fn from_message(mut msg: crate::Message<'de>) -> Self {
         msg.parameters.reverse();
         Self {
+            params: msg.parameters.len(),
             input: (Some(msg.command), msg.parameters),
+            tags: Vec::new(),
+            fields: 0,
+            greedy: false,
+            names: (&[], 0),
+        }
+    }
+
+    pub fn from_params(mut params: Vec<&'de str>) -> Self {
+        params.reverse();
+        Self {
+            params: params.len(),
+            input: (None, params),
+            tags: Vec::new(),
             fields: 0,
+            greedy: false,
+            names: (&[], 0),
         }
     }
+
+    // maps get the tags after the parameters, so a `#[serde(flatten)]` map can pick them up. the
+    // struct is read as a map then, so its fields need renaming to their index, the command at "0"
+    pub fn from_message_with_tags(mut msg: crate::Message<'de>) -> Self {
+        let mut tags = std::mem::take(&mut msg.tags);
+        tags.reverse();
+        Self {
+            tags,
+            ..Self::from_message(msg)
+        }
+    }
+
+    // for enums discriminated on the trailing parameter's first word instead of the command,
+    // like `PRIVMSG #irk :\x01ACTION waves\x01` once the CTCP delimiters are gone
+    pub fn from_trailing(msg: &crate::Message<'de>) -> Self {
+        let last = msg.parameters.last().copied().unwrap_or_default();
+        let (word, rest) = last.split_once(' ').unwrap_or((last, ""));
+        let rest = rest.trim_start_matches(' ');
+        let mut de = Self::from_params(match rest {
+            "" => vec![],
+            rest => vec![rest],
+        });
+        de.input.0 = Some(word);
+        de
+    }
 }
 
 impl<'de> Deserializer<'de> {
@@ -29,6 +109,28 @@ impl<'de> Deserializer<'de> {
     fn available(&self) -> usize {
         self.input.0.is_some() as usize + self.input.1.len()
     }
+
+    fn with_names<T>(
+        &mut self,
+        names: &'static [&'static str],
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.fields += names.len();
+        let outer = std::mem::replace(&mut self.names, (names, 0));
+        let v = f(self);
+        self.names = outer;
+        v
+    }
+
+    fn missing_field(&self, err: Error) -> Error {
+        match (err, self.names.0.get(self.names.1)) {
+            (Error::Eof, Some(name)) => Error::MissingField {
+                name,
+                at: self.params - self.input.1.len(),
+            },
+            (err, _) => err,
+        }
+    }
 }
 
 macro_rules! visits_fromstr {
@@ -39,14 +141,6 @@ macro_rules! visits_fromstr {
     };
 }
 
-macro_rules! unsupported {
-    ($($fun:ident)*) => {
-        $(fn $fun<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
-            Err(Error::UnsupportedType)
-        })*
-    };
-}
-
 macro_rules! forward_tuple {
     ($($fun:ident($($param:ident: $type:ty),*) { $len:expr })*) => {
         $(fn $fun<V: Visitor<'de>>(self, $($param: $type,)* visitor: V) -> Result<V::Value> {
@@ -55,22 +149,26 @@ macro_rules! forward_tuple {
     };
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     visits_fromstr! {
         deserialize_i8:visit_i8 deserialize_i16:visit_i16 deserialize_i32:visit_i32 deserialize_i64:visit_i64
         deserialize_u8:visit_u8 deserialize_u16:visit_u16 deserialize_u32:visit_u32 deserialize_u64:visit_u64
-        deserialize_f32:visit_f32 deserialize_f64:visit_f64 deserialize_char:visit_char deserialize_bool:visit_bool
+        deserialize_f32:visit_f32 deserialize_f64:visit_f64 deserialize_bool:visit_bool
+    }
+
+    // everything is a string on the wire, this is what `#[serde(flatten)]` buffers parameters as
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_borrowed_str(self.read_part()?)
     }
 
-    unsupported! {
-        deserialize_any deserialize_map deserialize_ignored_any
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_map(Indexed(self, 0, None))
     }
 
     forward_tuple! {
         deserialize_tuple_struct(_name: &'static str, len: usize) { len }
-        deserialize_struct(_name: &'static str, fields: &'static [&'static str]) { fields.len() }
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
@@ -78,7 +176,30 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         self.fields += len;
-        visitor.visit_seq(self)
+        self.with_names(&[], |de| visitor.visit_seq(de))
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.with_names(fields, |de| visitor.visit_seq(de))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let part = self.read_part()?;
+        let mut chars = part.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::Deserialize(format!(
+                "expected a single character, found {part:?}"
+            ))),
+        }
     }
 
     fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
@@ -89,16 +210,21 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_borrowed_str(self.read_part()?)
     }
 
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.read_part()?;
+        visitor.visit_unit()
+    }
+
     fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
         visitor.visit_bytes(self.read_part()?.as_bytes())
     }
 
     fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        visitor.visit_bytes(self.read_part()?.as_bytes())
+        visitor.visit_byte_buf(self.read_part()?.as_bytes().to_vec())
     }
 
     fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
-        if self.available() >= self.fields {
+        if self.available() > self.fields || (self.greedy && self.available() > 0) {
             visitor.visit_some(self)
         } else {
             visitor.visit_none()
@@ -116,11 +242,38 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_unit()
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_newtype_struct(self)
+        match name {
+            GREEDY => {
+                let outer = std::mem::replace(&mut self.greedy, true);
+                let v = visitor.visit_newtype_struct(&mut *self);
+                self.greedy = outer;
+                v
+            }
+            super::types::RAW_TAIL => {
+                let mut parts = Vec::new();
+                while let Ok(part) = self.read_part() {
+                    parts.push(part);
+                }
+                match parts.as_slice() {
+                    [] => Err(Error::Eof),
+                    [part] => visitor.visit_borrowed_str(part),
+                    parts => visitor.visit_string(parts.join(" ")),
+                }
+            }
+            super::types::SPACE_SEPARATED => {
+                let part = self.read_part()?;
+                visitor.visit_seq(Fields(self, part.split(' '), false))
+            }
+            super::types::NULL_SEPARATED => {
+                let part = self.read_part()?;
+                visitor.visit_seq(Fields(self, part.split('\0'), true))
+            }
+            _ => visitor.visit_newtype_struct(self),
+        }
     }
 
     fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
@@ -141,7 +294,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::EnumAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
     type Variant = Self;
 
@@ -153,7 +306,7 @@ impl<'de, 'a> de::EnumAccess<'de> for &'a mut Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> de::VariantAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
@@ -169,23 +322,97 @@ impl<'de, 'a> de::VariantAccess<'de> for &'a mut Deserializer<'de> {
 
     forward_tuple! {
         tuple_variant(len: usize) { len }
-        struct_variant(fields: &'static [&'static str]) { fields.len() }
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
     }
 }
 
-impl<'de, 'a> de::SeqAccess<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::SeqAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: de::DeserializeSeed<'de>,
     {
-        let v = seed.deserialize(&mut **self)?;
+        // the element stops counting as one of the fields still ahead of it, so that options
+        // nested in it see only what comes after the whole thing
         self.fields -= 1;
+        let v = seed
+            .deserialize(&mut **self)
+            .map_err(|e| self.missing_field(e))?;
+        self.names.1 += 1;
         Ok(Some(v))
     }
 }
 
+// maps take the rest of the parameters, keyed by their position from where the map starts, and
+// then any tags by their key
+struct Indexed<'de, 'a>(&'a mut Deserializer<'de>, usize, Option<&'de str>);
+
+impl<'de, 'a> de::MapAccess<'de> for Indexed<'de, 'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.0.available() == 0 {
+            let Some((key, value)) = self.0.tags.pop() else {
+                return Ok(None);
+            };
+            self.2 = Some(value);
+            return seed
+                .deserialize(BorrowedStrDeserializer::new(key))
+                .map(Some);
+        }
+        self.1 += 1;
+        seed.deserialize(Index(self.1 - 1)).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        match self.2.take().map(super::tags::unescape) {
+            Some(Cow::Borrowed(value)) => seed.deserialize(BorrowedStrDeserializer::new(value)),
+            Some(Cow::Owned(value)) => seed.deserialize(StringDeserializer::new(value)),
+            None => seed.deserialize(&mut *self.0),
+        }
+    }
+}
+
+struct Index(usize);
+
+impl<'de> de::Deserializer<'de> for Index {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u64(self.0 as u64)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_string(self.0.to_string())
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct enum ignored_any
+    }
+}
+
 struct Sequence<'de, 'a>(&'a mut Deserializer<'de>);
 
 impl<'de, 'a> de::SeqAccess<'de> for Sequence<'de, 'a> {
@@ -209,3 +436,201 @@ impl<'de, 'a> de::SeqAccess<'de> for Sequence<'de, 'a> {
         v
     }
 }
+
+// the bool keeps empty fields, which matter between `\0`s but are just extra spaces otherwise
+struct Fields<'de, 'a>(&'a mut Deserializer<'de>, std::str::Split<'de, char>, bool);
+
+impl<'de, 'a> de::SeqAccess<'de> for Fields<'de, 'a> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        let keep_empty = self.2;
+        let Some(field) = self.1.find(|field| keep_empty || !field.is_empty()) else {
+            return Ok(None);
+        };
+        self.0.input.0 = Some(field);
+        seed.deserialize(&mut *self.0).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::proto::IndexedParams;
+
+    #[test]
+    fn strict_char() {
+        let mut de = Deserializer::from_params(vec!["C", "LIST"]);
+        assert_eq!(char::deserialize(&mut de), Ok('C'));
+        assert_eq!(
+            char::deserialize(&mut de),
+            Err(Error::Deserialize(
+                "expected a single character, found \"LIST\"".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_field() {
+        let msg = crate::Message::from("KICK #irk");
+        assert_eq!(
+            crate::Command::deserialize(&mut Deserializer::from_message(msg)),
+            Err(Error::MissingField {
+                name: "users",
+                at: 1
+            })
+        );
+
+        let msg = crate::Message::from("TOPIC");
+        assert_eq!(
+            crate::Command::deserialize(&mut Deserializer::from_message(msg)),
+            Err(Error::MissingField {
+                name: "channel",
+                at: 0
+            })
+        );
+
+        let mut de = Deserializer::from_params(vec!["a"]);
+        assert_eq!(<(&str, &str)>::deserialize(&mut de), Err(Error::Eof));
+    }
+
+    #[test]
+    fn byte_buf() {
+        let mut de = Deserializer::from_params(vec!["PLAIN"]);
+        assert_eq!(
+            serde_bytes::ByteBuf::deserialize(&mut de),
+            Ok(serde_bytes::ByteBuf::from(*b"PLAIN"))
+        );
+    }
+
+    #[test]
+    fn other_variant() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        enum Known<'a> {
+            Nick {
+                nickname: &'a str,
+            },
+            Ping {
+                token: &'a str,
+            },
+            #[serde(other)]
+            Other,
+        }
+
+        for (line, known) in [
+            ("NICK rini", Known::Nick { nickname: "rini" }),
+            (
+                "PING :irc.example.com",
+                Known::Ping {
+                    token: "irc.example.com",
+                },
+            ),
+            ("WALLOPS :hello opers", Known::Other),
+            (":irc.example.com 001 rini :Welcome", Known::Other),
+        ] {
+            let msg = crate::Message::from(line);
+            assert_eq!(
+                Known::deserialize(&mut Deserializer::from_message(msg)),
+                Ok(known)
+            );
+        }
+    }
+
+    #[test]
+    fn from_trailing() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        enum Ctcp<'a> {
+            Version,
+            Action { text: &'a str },
+            Ping { token: Option<&'a str> },
+        }
+
+        let parse = |line| {
+            let msg = crate::Message::from(line);
+            Ctcp::deserialize(&mut Deserializer::from_trailing(&msg))
+        };
+
+        assert_eq!(parse("PRIVMSG #irk :VERSION"), Ok(Ctcp::Version));
+        assert_eq!(
+            parse("PRIVMSG #irk :ACTION waves at  everyone"),
+            Ok(Ctcp::Action {
+                text: "waves at  everyone"
+            })
+        );
+        assert_eq!(parse("NOTICE rini :PING"), Ok(Ctcp::Ping { token: None }));
+        assert_eq!(
+            parse("NOTICE rini :PING 1234"),
+            Ok(Ctcp::Ping {
+                token: Some("1234")
+            })
+        );
+        assert!(parse("PRIVMSG #irk :DANCE").is_err());
+    }
+
+    #[test]
+    fn flatten_tags() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Tagged<'a> {
+            #[serde(rename = "0")]
+            command: &'a str,
+            #[serde(rename = "1")]
+            target: &'a str,
+            #[serde(rename = "2")]
+            text: &'a str,
+            #[serde(flatten)]
+            tags: HashMap<String, String>,
+        }
+
+        let msg =
+            crate::Message::from("@id=42;+draft/reply=7;time=a\\sb :alice PRIVMSG #irk :hi there");
+        let tagged = Tagged::deserialize(&mut Deserializer::from_message_with_tags(msg)).unwrap();
+        assert_eq!(tagged.command, "PRIVMSG");
+        assert_eq!(tagged.target, "#irk");
+        assert_eq!(tagged.text, "hi there");
+        assert_eq!(
+            tagged.tags,
+            HashMap::from([
+                ("id".into(), "42".into()),
+                ("+draft/reply".into(), "7".into()),
+                ("time".into(), "a b".into()),
+            ])
+        );
+
+        let msg = crate::Message::from("@id=42 PRIVMSG #irk :hi");
+        let tagged = Tagged::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        assert!(tagged.tags.is_empty());
+    }
+
+    #[test]
+    fn indexed_params() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Raw<'a> {
+            command: &'a str,
+            #[serde(borrow)]
+            params: IndexedParams<'a>,
+        }
+
+        let msg = crate::Message::from("MODE #irk +o :alice");
+        let raw = Raw::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        assert_eq!(raw.command, "MODE");
+        assert_eq!(
+            raw.params,
+            IndexedParams::from([(0, "#irk"), (1, "+o"), (2, "alice")])
+        );
+
+        let mut de = Deserializer::from_params(vec![]);
+        assert_eq!(
+            IndexedParams::deserialize(&mut de),
+            Ok(IndexedParams::new())
+        );
+    }
+}
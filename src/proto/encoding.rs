@@ -0,0 +1,53 @@
+use std::borrow::Cow;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoding {
+    Ascii,
+    Utf8,
+    Latin1,
+    Unknown,
+}
+
+pub fn detect_encoding(bytes: &[u8]) -> Encoding {
+    if bytes.is_ascii() {
+        Encoding::Ascii
+    } else if std::str::from_utf8(bytes).is_ok() {
+        Encoding::Utf8
+    } else if bytes.iter().any(|b| (0x80..0xa0).contains(b)) {
+        // C1 controls basically never show up in latin-1 text, so this is probably
+        // cp1252 or just garbage
+        Encoding::Unknown
+    } else {
+        Encoding::Latin1
+    }
+}
+
+impl Encoding {
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        match self {
+            Self::Latin1 => Cow::Owned(bytes.iter().map(|&b| b as char).collect()),
+            _ => String::from_utf8_lossy(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect() {
+        assert_eq!(detect_encoding(b"PRIVMSG #irk :hi"), Encoding::Ascii);
+
+        let utf8 = "PRIVMSG #irk :olá, 世界".as_bytes();
+        assert_eq!(detect_encoding(utf8), Encoding::Utf8);
+        assert_eq!(Encoding::Utf8.decode(utf8), "PRIVMSG #irk :olá, 世界");
+
+        let latin1 = b"PRIVMSG #irk :ol\xe1 caf\xe9";
+        assert_eq!(detect_encoding(latin1), Encoding::Latin1);
+        assert_eq!(Encoding::Latin1.decode(latin1), "PRIVMSG #irk :olá café");
+
+        assert_eq!(detect_encoding(b"\x93quoted\x94 \xe9"), Encoding::Unknown);
+        assert_eq!(Encoding::Unknown.decode(b"a\x93"), "a\u{fffd}");
+    }
+}
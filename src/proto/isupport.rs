@@ -0,0 +1,187 @@
+use super::{mode::ChanModes, nick, CaseMapping};
+use crate::Message;
+
+// the RPL_ISUPPORT tokens clients tend to need, with what to assume until the server says otherwise
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ServerInfo {
+    pub network: Option<String>,
+    pub chantypes: String,
+    pub chanmodes: ChanModes,
+    pub nicklen: usize,
+    pub channellen: usize,
+    // `None` when the server takes any number of modes per MODE
+    pub modes: Option<u32>,
+    pub casemapping: CaseMapping,
+}
+
+impl Default for ServerInfo {
+    fn default() -> Self {
+        Self {
+            network: None,
+            chantypes: "#&".into(),
+            chanmodes: ChanModes::default(),
+            nicklen: nick::MAX_LEN,
+            channellen: 50,
+            modes: Some(3),
+            casemapping: CaseMapping::default(),
+        }
+    }
+}
+
+impl ServerInfo {
+    // servers split their tokens over as many 005 lines as they like
+    pub fn feed(&mut self, msg: &Message) -> bool {
+        let ("005", [_, tokens @ .., _]) = (msg.command, msg.parameters.as_slice()) else {
+            return false;
+        };
+        for token in tokens {
+            match token.strip_prefix('-') {
+                Some(key) => self.reset(key),
+                None => {
+                    let (key, value) = token.split_once('=').unwrap_or((token, ""));
+                    self.set(key, value);
+                }
+            }
+        }
+        true
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "NETWORK" => self.network = Some(value.into()),
+            "CHANTYPES" => self.chantypes = value.into(),
+            "CHANMODES" => {
+                if let Some(modes) = ChanModes::parse(value, "") {
+                    let prefixes = std::mem::take(&mut self.chanmodes.prefixes);
+                    self.chanmodes = ChanModes { prefixes, ..modes };
+                }
+            }
+            "PREFIX" => {
+                if let Some(modes) = ChanModes::parse(",,,", value) {
+                    self.chanmodes.prefixes = modes.prefixes;
+                }
+            }
+            "NICKLEN" => self.nicklen = value.parse().unwrap_or(self.nicklen),
+            "CHANNELLEN" => self.channellen = value.parse().unwrap_or(self.channellen),
+            "MODES" => self.modes = value.parse().ok(),
+            "CASEMAPPING" => {
+                self.casemapping = CaseMapping::from_isupport(value).unwrap_or(self.casemapping)
+            }
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self, key: &str) {
+        let default = Self::default();
+        match key {
+            "NETWORK" => self.network = default.network,
+            "CHANTYPES" => self.chantypes = default.chantypes,
+            "CHANMODES" => {
+                let prefixes = std::mem::take(&mut self.chanmodes.prefixes);
+                self.chanmodes = ChanModes {
+                    prefixes,
+                    ..default.chanmodes
+                };
+            }
+            "PREFIX" => self.chanmodes.prefixes = default.chanmodes.prefixes,
+            "NICKLEN" => self.nicklen = default.nicklen,
+            "CHANNELLEN" => self.channellen = default.channellen,
+            "MODES" => self.modes = default.modes,
+            "CASEMAPPING" => self.casemapping = default.casemapping,
+            _ => {}
+        }
+    }
+}
+
+fn pairs(value: &str) -> impl Iterator<Item = Option<(&str, &str)>> {
+    value.split(',').map(|pair| pair.split_once(':'))
+}
+
+// `MAXLIST=beI:100,w:50`
+pub fn maxlist(value: &str) -> Option<Vec<(&str, u32)>> {
+    pairs(value)
+        .map(|pair| {
+            let (modes, limit) = pair?;
+            Some((modes, limit.parse().ok()?))
+        })
+        .collect()
+}
+
+// `CHANLIMIT=#&:50,!:`, where an empty limit means no limit
+pub fn chanlimit(value: &str) -> Option<Vec<(&str, Option<u32>)>> {
+    pairs(value)
+        .map(|pair| match pair? {
+            (types, "") => Some((types, None)),
+            (types, limit) => Some((types, Some(limit.parse().ok()?))),
+        })
+        .collect()
+}
+
+// splits a `@#chan` target by the STATUSMSG prefixes, e.g. `@+`
+pub fn status_target<'a>(target: &'a str, statusmsg: &str) -> (Option<char>, &'a str) {
+    match target.chars().next() {
+        Some(c) if statusmsg.contains(c) => (Some(c), &target[c.len_utf8()..]),
+        _ => (None, target),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        assert_eq!(maxlist("beI:100,w:50"), Some(vec![("beI", 100), ("w", 50)]));
+        assert_eq!(maxlist("b:lots"), None);
+        assert_eq!(maxlist("b"), None);
+
+        assert_eq!(chanlimit("#:25"), Some(vec![("#", Some(25))]));
+        assert_eq!(
+            chanlimit("#&:50,!:"),
+            Some(vec![("#&", Some(50)), ("!", None)])
+        );
+    }
+
+    #[test]
+    fn status_targets() {
+        assert_eq!(status_target("@#irk", "@+"), (Some('@'), "#irk"));
+        assert_eq!(status_target("+#irk", "@+"), (Some('+'), "#irk"));
+        assert_eq!(status_target("#irk", "@+"), (None, "#irk"));
+        assert_eq!(status_target("+#irk", ""), (None, "+#irk"));
+    }
+
+    #[test]
+    fn server_info() {
+        let mut info = ServerInfo::default();
+        assert_eq!(info.modes, Some(3));
+
+        let msg = Message::from(
+            ":irc.example.com 005 rini NETWORK=Example CHANTYPES=# NICKLEN=30 MODES :are supported by this server",
+        );
+        assert!(info.feed(&msg));
+        let msg = Message::from(
+            ":irc.example.com 005 rini CHANMODES=beI,k,l,imnst PREFIX=(qov)~@+ CASEMAPPING=ascii CHANNELLEN=64 :are supported by this server",
+        );
+        assert!(info.feed(&msg));
+
+        assert_eq!(info.network.as_deref(), Some("Example"));
+        assert_eq!(info.chantypes, "#");
+        assert_eq!(
+            info.chanmodes,
+            ChanModes::parse("beI,k,l,imnst", "(qov)~@+").unwrap()
+        );
+        assert_eq!(info.nicklen, 30);
+        assert_eq!(
+            nick::fallback("averylongnickname", 1, info.nicklen).as_deref(),
+            Some("averylongnickname_")
+        );
+        assert_eq!(info.channellen, 64);
+        assert_eq!(info.modes, None);
+        assert_eq!(info.casemapping, CaseMapping::Ascii);
+
+        let msg = Message::from(":irc.example.com 005 rini -NETWORK :are supported by this server");
+        assert!(info.feed(&msg));
+        assert_eq!(info.network, None);
+        assert!(!info.feed(&Message::from("PING :x")));
+    }
+}
@@ -1,31 +1,101 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::Message;
+
+pub mod bot;
+pub mod cap;
+pub mod casemap;
 pub mod de;
+pub mod encoding;
+pub mod isupport;
+pub mod mode;
+pub mod names;
+pub mod nick;
+pub mod numeric;
+pub mod registry;
+pub mod sasl;
 pub mod ser;
+pub mod state;
+pub mod tags;
+pub mod types;
+pub mod who;
+pub mod whois;
 
+pub use casemap::{normalize_channel, CaseMapping};
 pub use de::Deserializer;
+pub use encoding::{detect_encoding, Encoding};
+pub use registry::CommandRegistry;
 pub use ser::Serializer;
+pub use types::{
+    CommaSeparated, IndexedParams, NullSeparated, Parsed, PercentDecoded, Placeholder, RawTail,
+    Seconds, Signed, SpaceSeparated, Trailing,
+};
+
+pub fn parse_owned_many(buffer: &str) -> Vec<crate::Result<crate::MessageBuf>> {
+    (buffer.split('\n'))
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .map(|line| match Message::parse_strict(line)? {
+            msg if msg.command.is_empty() => Err(crate::Error::InvalidCommand),
+            msg => Ok(crate::MessageBuf::from(&msg)),
+        })
+        .collect()
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum Command<'a> {
     Cap(Cap<'a>),
+    // a mechanism and a chunk look the same on the wire, so parsing always gives this one and
+    // `sasl_chunk` reads it as a chunk for whoever knows that's what it is
     Authenticate {
         mechanism: &'a str,
     },
+    #[serde(rename = "AUTHENTICATE", skip_deserializing)]
+    AuthenticateChunk {
+        chunk: sasl::Chunk<'a>,
+    },
+    Ping {
+        token: &'a str,
+        target: Option<&'a str>,
+    },
     Pong {
         server: Option<&'a str>,
         token: &'a str,
     },
+    Pass {
+        password: &'a str,
+    },
     Nick {
         nickname: &'a str,
     },
+    Privmsg {
+        targets: Vec<&'a str>,
+        text: Trailing<&'a str>,
+    },
+    Notice {
+        targets: Vec<&'a str>,
+        text: Trailing<&'a str>,
+    },
+    Tagmsg {
+        target: &'a str,
+    },
     User {
         username: &'a str,
         realname: &'a str,
     },
+    Join {
+        channels: Vec<&'a str>,
+        keys: Option<Vec<&'a str>>,
+    },
+    Part {
+        channels: Vec<&'a str>,
+        reason: Option<&'a str>,
+    },
     Kick {
-        channel: &'a str,
+        channels: Vec<&'a str>,
         users: Vec<&'a str>,
         reason: Option<&'a str>,
     },
@@ -33,10 +103,1268 @@ pub enum Command<'a> {
         channel: &'a str,
         topic: Option<&'a str>,
     },
+    Oper {
+        name: &'a str,
+        password: &'a str,
+    },
+    Away {
+        message: Option<&'a str>,
+    },
+    Invite {
+        nickname: &'a str,
+        channel: &'a str,
+    },
+    Silence {
+        mask: Option<Signed<'a>>,
+    },
+    #[serde(serialize_with = "ser::greedy", deserialize_with = "de::greedy")]
+    Lusers {
+        mask: Option<&'a str>,
+        target: Option<&'a str>,
+    },
+    Trace {
+        target: Option<&'a str>,
+    },
+    Info {
+        target: Option<&'a str>,
+    },
+    Help {
+        subject: Option<&'a str>,
+    },
+    Users {
+        target: Option<&'a str>,
+    },
+    #[serde(serialize_with = "ser::greedy", deserialize_with = "de::greedy")]
+    Stats {
+        query: Option<char>,
+        target: Option<&'a str>,
+    },
+    Setname {
+        realname: &'a str,
+    },
+    Chghost {
+        username: &'a str,
+        host: &'a str,
+    },
+    Rename {
+        old: &'a str,
+        new: &'a str,
+        reason: Option<&'a str>,
+    },
+    #[cfg(feature = "server")]
+    Kill {
+        nickname: &'a str,
+        comment: &'a str,
+    },
+    #[cfg(feature = "server")]
+    Kline {
+        duration: Seconds,
+        mask: &'a str,
+        reason: Option<&'a str>,
+    },
+    #[cfg(feature = "server")]
+    Gline {
+        duration: Seconds,
+        mask: &'a str,
+        reason: Option<&'a str>,
+    },
+    Metadata {
+        target: &'a str,
+        subcommand: Metadata<'a>,
+    },
+    Register {
+        account: &'a str,
+        email: &'a str,
+        password: &'a str,
+    },
+    Verify {
+        account: &'a str,
+        code: &'a str,
+    },
+    // without a timestamp this asks the server for the current one
+    Markread {
+        target: &'a str,
+        #[serde(with = "timestamp")]
+        timestamp: Option<&'a str>,
+    },
+}
+
+// `timestamp=...` as its own parameter, like the read-marker spec puts it
+mod timestamp {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<&str>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(ts) => serializer.collect_str(&format_args!("timestamp={ts}")),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<&'de str>, D::Error> {
+        // servers reply with `*` when there's no read marker yet
+        match Option::<&str>::deserialize(deserializer)? {
+            Some("*") | None => Ok(None),
+            Some(v) => Ok(Some(v.strip_prefix("timestamp=").unwrap_or(v))),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
 pub enum Cap<'a> {
     Req { caps: &'a str },
     End,
 }
+
+// a Command that owns its parameters, for keeping around past the line it came from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OwnedCommand {
+    Cap(OwnedCap),
+    Authenticate {
+        mechanism: String,
+    },
+    AuthenticateChunk {
+        // `+`, `*` or the data, like `sasl::Chunk::as_str` gives it
+        chunk: String,
+    },
+    Ping {
+        token: String,
+        target: Option<String>,
+    },
+    Pong {
+        server: Option<String>,
+        token: String,
+    },
+    Pass {
+        password: String,
+    },
+    Nick {
+        nickname: String,
+    },
+    Privmsg {
+        targets: Vec<String>,
+        text: String,
+    },
+    Notice {
+        targets: Vec<String>,
+        text: String,
+    },
+    Tagmsg {
+        target: String,
+    },
+    User {
+        username: String,
+        realname: String,
+    },
+    Join {
+        channels: Vec<String>,
+        keys: Option<Vec<String>>,
+    },
+    Part {
+        channels: Vec<String>,
+        reason: Option<String>,
+    },
+    Kick {
+        channels: Vec<String>,
+        users: Vec<String>,
+        reason: Option<String>,
+    },
+    Topic {
+        channel: String,
+        topic: Option<String>,
+    },
+    Oper {
+        name: String,
+        password: String,
+    },
+    Away {
+        message: Option<String>,
+    },
+    Invite {
+        nickname: String,
+        channel: String,
+    },
+    Silence {
+        // `Signed`'s add and value
+        mask: Option<(bool, String)>,
+    },
+    Lusers {
+        mask: Option<String>,
+        target: Option<String>,
+    },
+    Trace {
+        target: Option<String>,
+    },
+    Info {
+        target: Option<String>,
+    },
+    Help {
+        subject: Option<String>,
+    },
+    Users {
+        target: Option<String>,
+    },
+    Stats {
+        query: Option<char>,
+        target: Option<String>,
+    },
+    Setname {
+        realname: String,
+    },
+    Chghost {
+        username: String,
+        host: String,
+    },
+    Rename {
+        old: String,
+        new: String,
+        reason: Option<String>,
+    },
+    #[cfg(feature = "server")]
+    Kill {
+        nickname: String,
+        comment: String,
+    },
+    #[cfg(feature = "server")]
+    Kline {
+        duration: Seconds,
+        mask: String,
+        reason: Option<String>,
+    },
+    #[cfg(feature = "server")]
+    Gline {
+        duration: Seconds,
+        mask: String,
+        reason: Option<String>,
+    },
+    Metadata {
+        target: String,
+        subcommand: OwnedMetadata,
+    },
+    Register {
+        account: String,
+        email: String,
+        password: String,
+    },
+    Verify {
+        account: String,
+        code: String,
+    },
+    Markread {
+        target: String,
+        timestamp: Option<String>,
+    },
+}
+
+impl OwnedCommand {
+    pub fn borrow(&self) -> Command<'_> {
+        match self {
+            Self::Cap(cap) => Command::Cap(cap.borrow()),
+            Self::Authenticate { mechanism } => Command::Authenticate { mechanism },
+            Self::AuthenticateChunk { chunk } => Command::AuthenticateChunk {
+                chunk: sasl::Chunk::from(chunk.as_str()),
+            },
+            Self::Ping { token, target } => Command::Ping {
+                token,
+                target: target.as_deref(),
+            },
+            Self::Pong { server, token } => Command::Pong {
+                server: server.as_deref(),
+                token,
+            },
+            Self::Pass { password } => Command::Pass { password },
+            Self::Nick { nickname } => Command::Nick { nickname },
+            Self::Privmsg { targets, text } => Command::Privmsg {
+                targets: targets.iter().map(String::as_str).collect(),
+                text: Trailing(text.as_str()),
+            },
+            Self::Notice { targets, text } => Command::Notice {
+                targets: targets.iter().map(String::as_str).collect(),
+                text: Trailing(text.as_str()),
+            },
+            Self::Tagmsg { target } => Command::Tagmsg { target },
+            Self::User { username, realname } => Command::User { username, realname },
+            Self::Join { channels, keys } => Command::Join {
+                channels: channels.iter().map(String::as_str).collect(),
+                keys: keys
+                    .as_ref()
+                    .map(|v| v.iter().map(String::as_str).collect()),
+            },
+            Self::Part { channels, reason } => Command::Part {
+                channels: channels.iter().map(String::as_str).collect(),
+                reason: reason.as_deref(),
+            },
+            Self::Kick {
+                channels,
+                users,
+                reason,
+            } => Command::Kick {
+                channels: channels.iter().map(String::as_str).collect(),
+                users: users.iter().map(String::as_str).collect(),
+                reason: reason.as_deref(),
+            },
+            Self::Topic { channel, topic } => Command::Topic {
+                channel,
+                topic: topic.as_deref(),
+            },
+            Self::Oper { name, password } => Command::Oper { name, password },
+            Self::Away { message } => Command::Away {
+                message: message.as_deref(),
+            },
+            Self::Invite { nickname, channel } => Command::Invite { nickname, channel },
+            Self::Silence { mask } => Command::Silence {
+                mask: mask
+                    .as_ref()
+                    .map(|(add, value)| Signed { add: *add, value }),
+            },
+            Self::Lusers { mask, target } => Command::Lusers {
+                mask: mask.as_deref(),
+                target: target.as_deref(),
+            },
+            Self::Trace { target } => Command::Trace {
+                target: target.as_deref(),
+            },
+            Self::Info { target } => Command::Info {
+                target: target.as_deref(),
+            },
+            Self::Help { subject } => Command::Help {
+                subject: subject.as_deref(),
+            },
+            Self::Users { target } => Command::Users {
+                target: target.as_deref(),
+            },
+            Self::Stats { query, target } => Command::Stats {
+                query: *query,
+                target: target.as_deref(),
+            },
+            Self::Setname { realname } => Command::Setname { realname },
+            Self::Chghost { username, host } => Command::Chghost { username, host },
+            Self::Rename { old, new, reason } => Command::Rename {
+                old,
+                new,
+                reason: reason.as_deref(),
+            },
+            #[cfg(feature = "server")]
+            Self::Kill { nickname, comment } => Command::Kill { nickname, comment },
+            #[cfg(feature = "server")]
+            Self::Kline {
+                duration,
+                mask,
+                reason,
+            } => Command::Kline {
+                duration: *duration,
+                mask,
+                reason: reason.as_deref(),
+            },
+            #[cfg(feature = "server")]
+            Self::Gline {
+                duration,
+                mask,
+                reason,
+            } => Command::Gline {
+                duration: *duration,
+                mask,
+                reason: reason.as_deref(),
+            },
+            Self::Metadata { target, subcommand } => Command::Metadata {
+                target,
+                subcommand: subcommand.borrow(),
+            },
+            Self::Register {
+                account,
+                email,
+                password,
+            } => Command::Register {
+                account,
+                email,
+                password,
+            },
+            Self::Verify { account, code } => Command::Verify { account, code },
+            Self::Markread { target, timestamp } => Command::Markread {
+                target,
+                timestamp: timestamp.as_deref(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OwnedCap {
+    Req { caps: String },
+    End,
+}
+
+impl OwnedCap {
+    pub fn borrow(&self) -> Cap<'_> {
+        match self {
+            Self::Req { caps } => Cap::Req { caps },
+            Self::End => Cap::End,
+        }
+    }
+}
+
+impl Cap<'_> {
+    pub fn to_owned_cap(&self) -> OwnedCap {
+        match *self {
+            Self::Req { caps } => OwnedCap::Req { caps: caps.into() },
+            Self::End => OwnedCap::End,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Metadata<'a> {
+    Get {
+        key: &'a str,
+    },
+    List,
+    Set {
+        key: &'a str,
+        value: Option<&'a str>,
+    },
+    Clear,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OwnedMetadata {
+    Get { key: String },
+    List,
+    Set { key: String, value: Option<String> },
+    Clear,
+}
+
+impl OwnedMetadata {
+    pub fn borrow(&self) -> Metadata<'_> {
+        match self {
+            Self::Get { key } => Metadata::Get { key },
+            Self::List => Metadata::List,
+            Self::Set { key, value } => Metadata::Set {
+                key,
+                value: value.as_deref(),
+            },
+            Self::Clear => Metadata::Clear,
+        }
+    }
+}
+
+impl Metadata<'_> {
+    pub fn to_owned_metadata(&self) -> OwnedMetadata {
+        match *self {
+            Self::Get { key } => OwnedMetadata::Get { key: key.into() },
+            Self::List => OwnedMetadata::List,
+            Self::Set { key, value } => OwnedMetadata::Set {
+                key: key.into(),
+                value: value.map(Into::into),
+            },
+            Self::Clear => OwnedMetadata::Clear,
+        }
+    }
+}
+
+impl<'a> Command<'a> {
+    pub fn to_owned_command(&self) -> OwnedCommand {
+        match self {
+            Self::Cap(cap) => OwnedCommand::Cap(cap.to_owned_cap()),
+            Self::Authenticate { mechanism } => OwnedCommand::Authenticate {
+                mechanism: mechanism.to_string(),
+            },
+            Self::AuthenticateChunk { chunk } => OwnedCommand::AuthenticateChunk {
+                chunk: chunk.as_str().into(),
+            },
+            Self::Ping { token, target } => OwnedCommand::Ping {
+                token: token.to_string(),
+                target: target.map(String::from),
+            },
+            Self::Pong { server, token } => OwnedCommand::Pong {
+                server: server.map(String::from),
+                token: token.to_string(),
+            },
+            Self::Pass { password } => OwnedCommand::Pass {
+                password: password.to_string(),
+            },
+            Self::Nick { nickname } => OwnedCommand::Nick {
+                nickname: nickname.to_string(),
+            },
+            Self::Privmsg { targets, text } => OwnedCommand::Privmsg {
+                targets: targets.iter().map(|s| s.to_string()).collect(),
+                text: text.0.to_string(),
+            },
+            Self::Notice { targets, text } => OwnedCommand::Notice {
+                targets: targets.iter().map(|s| s.to_string()).collect(),
+                text: text.0.to_string(),
+            },
+            Self::Tagmsg { target } => OwnedCommand::Tagmsg {
+                target: target.to_string(),
+            },
+            Self::User { username, realname } => OwnedCommand::User {
+                username: username.to_string(),
+                realname: realname.to_string(),
+            },
+            Self::Join { channels, keys } => OwnedCommand::Join {
+                channels: channels.iter().map(|s| s.to_string()).collect(),
+                keys: keys
+                    .as_ref()
+                    .map(|v| v.iter().map(|s| s.to_string()).collect()),
+            },
+            Self::Part { channels, reason } => OwnedCommand::Part {
+                channels: channels.iter().map(|s| s.to_string()).collect(),
+                reason: reason.map(String::from),
+            },
+            Self::Kick {
+                channels,
+                users,
+                reason,
+            } => OwnedCommand::Kick {
+                channels: channels.iter().map(|s| s.to_string()).collect(),
+                users: users.iter().map(|s| s.to_string()).collect(),
+                reason: reason.map(String::from),
+            },
+            Self::Topic { channel, topic } => OwnedCommand::Topic {
+                channel: channel.to_string(),
+                topic: topic.map(String::from),
+            },
+            Self::Oper { name, password } => OwnedCommand::Oper {
+                name: name.to_string(),
+                password: password.to_string(),
+            },
+            Self::Away { message } => OwnedCommand::Away {
+                message: message.map(String::from),
+            },
+            Self::Invite { nickname, channel } => OwnedCommand::Invite {
+                nickname: nickname.to_string(),
+                channel: channel.to_string(),
+            },
+            Self::Silence { mask } => OwnedCommand::Silence {
+                mask: mask.map(|s| (s.add, s.value.into())),
+            },
+            Self::Lusers { mask, target } => OwnedCommand::Lusers {
+                mask: mask.map(String::from),
+                target: target.map(String::from),
+            },
+            Self::Trace { target } => OwnedCommand::Trace {
+                target: target.map(String::from),
+            },
+            Self::Info { target } => OwnedCommand::Info {
+                target: target.map(String::from),
+            },
+            Self::Help { subject } => OwnedCommand::Help {
+                subject: subject.map(String::from),
+            },
+            Self::Users { target } => OwnedCommand::Users {
+                target: target.map(String::from),
+            },
+            Self::Stats { query, target } => OwnedCommand::Stats {
+                query: *query,
+                target: target.map(String::from),
+            },
+            Self::Setname { realname } => OwnedCommand::Setname {
+                realname: realname.to_string(),
+            },
+            Self::Chghost { username, host } => OwnedCommand::Chghost {
+                username: username.to_string(),
+                host: host.to_string(),
+            },
+            Self::Rename { old, new, reason } => OwnedCommand::Rename {
+                old: old.to_string(),
+                new: new.to_string(),
+                reason: reason.map(String::from),
+            },
+            #[cfg(feature = "server")]
+            Self::Kill { nickname, comment } => OwnedCommand::Kill {
+                nickname: nickname.to_string(),
+                comment: comment.to_string(),
+            },
+            #[cfg(feature = "server")]
+            Self::Kline {
+                duration,
+                mask,
+                reason,
+            } => OwnedCommand::Kline {
+                duration: *duration,
+                mask: mask.to_string(),
+                reason: reason.map(String::from),
+            },
+            #[cfg(feature = "server")]
+            Self::Gline {
+                duration,
+                mask,
+                reason,
+            } => OwnedCommand::Gline {
+                duration: *duration,
+                mask: mask.to_string(),
+                reason: reason.map(String::from),
+            },
+            Self::Metadata { target, subcommand } => OwnedCommand::Metadata {
+                target: target.to_string(),
+                subcommand: subcommand.to_owned_metadata(),
+            },
+            Self::Register {
+                account,
+                email,
+                password,
+            } => OwnedCommand::Register {
+                account: account.to_string(),
+                email: email.to_string(),
+                password: password.to_string(),
+            },
+            Self::Verify { account, code } => OwnedCommand::Verify {
+                account: account.to_string(),
+                code: code.to_string(),
+            },
+            Self::Markread { target, timestamp } => OwnedCommand::Markread {
+                target: target.to_string(),
+                timestamp: timestamp.map(String::from),
+            },
+        }
+    }
+
+    pub fn to_message_buf(&self) -> crate::Result<crate::MessageBuf> {
+        let ser = Serializer::new(self)?;
+        Ok(crate::MessageBuf::from(&ser.to_message()?))
+    }
+
+    pub fn to_redacted_string(&self) -> String {
+        const REDACTED: &str = "****";
+
+        let cmd = match *self {
+            Self::Pass { .. } => Self::Pass { password: REDACTED },
+            Self::Oper { name, .. } => Self::Oper {
+                name,
+                password: REDACTED,
+            },
+            Self::Register { account, email, .. } => Self::Register {
+                account,
+                email,
+                password: REDACTED,
+            },
+            // a mechanism name can't be told apart from a chunk of the password
+            Self::Authenticate { .. } | Self::AuthenticateChunk { .. }
+                if matches!(self.sasl_chunk(), Some(sasl::Chunk::Data(_))) =>
+            {
+                Self::AuthenticateChunk {
+                    chunk: sasl::Chunk::Data(REDACTED),
+                }
+            }
+            ref cmd => cmd.clone(),
+        };
+
+        let ser = Serializer::new(cmd).expect("commands always serialize");
+        ser.to_message().map(|m| m.to_string()).unwrap_or_default()
+    }
+
+    // each PRIVMSG or NOTICE target split by the server's STATUSMSG prefixes
+    pub fn targets_detailed<'s>(
+        &'s self,
+        statusmsg: &'s str,
+    ) -> impl Iterator<Item = (Option<char>, &'a str)> + 's {
+        let targets = match self {
+            Self::Privmsg { targets, .. } | Self::Notice { targets, .. } => targets.as_slice(),
+            _ => &[],
+        };
+        (targets.iter()).map(move |target| isupport::status_target(target, statusmsg))
+    }
+
+    pub fn sasl_chunk(&self) -> Option<sasl::Chunk<'a>> {
+        match *self {
+            Self::Authenticate { mechanism } => Some(sasl::Chunk::from(mechanism)),
+            Self::AuthenticateChunk { chunk } => Some(chunk),
+            _ => None,
+        }
+    }
+
+    pub fn chghost_prefix(&self, source: &str) -> Option<String> {
+        match self {
+            Self::Chghost { username, host } => {
+                let nick = source.split(['!', '@']).next().unwrap_or(source);
+                Some(format!("{nick}!{username}@{host}"))
+            }
+            _ => None,
+        }
+    }
+
+    // moves whatever was tracked under the old channel name over to the new one
+    pub fn rename_channel<V>(&self, channels: &mut HashMap<String, V>) -> bool {
+        let Self::Rename { old, new, .. } = self else {
+            return false;
+        };
+        match channels.remove(*old) {
+            Some(v) => {
+                channels.insert(new.to_string(), v);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // `JOIN 0` parts every channel instead of joining one called "0"
+    pub fn part_all() -> Self {
+        Self::Join {
+            channels: vec!["0"],
+            keys: None,
+        }
+    }
+
+    pub fn is_part_all(&self) -> bool {
+        matches!(self, Self::Join { channels, keys: None } if channels[..] == ["0"])
+    }
+
+    pub fn pong(&self) -> Option<Command<'a>> {
+        match *self {
+            Self::Ping { token, target } => Some(Self::Pong {
+                server: target,
+                token,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    macro_rules! test_roundtrip {
+        ($name:ident; $($in:literal => $out:expr),*) => {
+            #[test]
+            fn $name() {
+                $(
+                    let msg = Message::from($in);
+                    let cmd = Command::deserialize(&mut Deserializer::from_message(msg));
+                    assert_eq!(cmd, Ok($out));
+
+                    let ser = Serializer::new($out).unwrap();
+                    let line = ser.to_message().unwrap().to_string();
+                    let msg = Message::from(line.as_str());
+                    assert_eq!(Command::deserialize(&mut Deserializer::from_message(msg)), Ok($out));
+                )*
+            }
+        };
+    }
+
+    test_roundtrip! {
+        ping;
+        "PING :tok" => Command::Ping { token: "tok", target: None },
+        "PING serverA serverB" => Command::Ping {
+            token: "serverA",
+            target: Some("serverB"),
+        }
+    }
+
+    #[test]
+    fn to_message_buf() {
+        let cmd = Command::Privmsg {
+            targets: vec!["#irk"],
+            text: Trailing("hello"),
+        };
+        let mut buf = cmd.to_message_buf().unwrap();
+        buf.parameters[0] = "#rust".into();
+        assert_eq!(buf.to_string(), "PRIVMSG #rust :hello");
+    }
+
+    #[test]
+    fn owned_command() {
+        let owned = {
+            let line = String::from("KICK #irk,#rust alice :bye now");
+            let cmd = Command::deserialize(&mut Deserializer::from_message(Message::from(
+                line.as_str(),
+            )))
+            .unwrap();
+            cmd.to_owned_command()
+        };
+
+        assert_eq!(
+            owned.borrow(),
+            Command::Kick {
+                channels: vec!["#irk", "#rust"],
+                users: vec!["alice"],
+                reason: Some("bye now"),
+            }
+        );
+
+        // the mirror copies options as they are, even ones the serializer would turn down
+        let cmds = [
+            Command::Stats {
+                query: None,
+                target: Some("srv"),
+            },
+            Command::Lusers {
+                mask: None,
+                target: Some("srv"),
+            },
+            Command::Metadata {
+                target: "*",
+                subcommand: Metadata::Set {
+                    key: "url",
+                    value: None,
+                },
+            },
+        ];
+        for cmd in cmds {
+            assert_eq!(cmd.to_owned_command().borrow(), cmd);
+        }
+    }
+
+    #[test]
+    fn redacted() {
+        let oper = Command::Oper {
+            name: "rini",
+            password: "hunter2",
+        };
+        assert_eq!(oper.to_redacted_string(), "OPER rini :****");
+
+        let pass = Command::Pass {
+            password: "hunter2",
+        };
+        assert_eq!(pass.to_redacted_string(), "PASS :****");
+
+        let auth = Command::AuthenticateChunk {
+            chunk: sasl::Chunk::Data("cmluaQBya"),
+        };
+        assert_eq!(auth.to_redacted_string(), "AUTHENTICATE :****");
+        // "ABC", which looks just like a mechanism name
+        let auth = Command::deserialize(&mut Deserializer::from_message(Message::from(
+            "AUTHENTICATE QUJD",
+        )))
+        .unwrap();
+        assert_eq!(auth.to_redacted_string(), "AUTHENTICATE :****");
+        let auth = Command::AuthenticateChunk {
+            chunk: sasl::Chunk::Empty,
+        };
+        assert_eq!(auth.to_redacted_string(), "AUTHENTICATE :+");
+        let auth = Command::Authenticate {
+            mechanism: "SCRAM-SHA-256",
+        };
+        assert_eq!(auth.to_redacted_string(), "AUTHENTICATE :****");
+        let auth = Command::Authenticate { mechanism: "*" };
+        assert_eq!(auth.to_redacted_string(), "AUTHENTICATE :*");
+
+        let nick = Command::Nick { nickname: "rini" };
+        assert_eq!(nick.to_redacted_string(), "NICK :rini");
+    }
+
+    test_roundtrip! {
+        oper;
+        "OPER rini hunter2" => Command::Oper {
+            name: "rini",
+            password: "hunter2",
+        },
+        "PASS :hunter2" => Command::Pass { password: "hunter2" }
+    }
+
+    #[test]
+    fn owned_many() {
+        let msgs = {
+            let buffer = String::from(
+                "@time=2024-01-01T00:00:00Z :a!a@h PRIVMSG #irk :one\r\n\r\n:b!b@h PRIVMSG #irk :two\n@x \r\n",
+            );
+            parse_owned_many(&buffer)
+        };
+
+        assert_eq!(msgs.len(), 3);
+        let first = msgs[0].as_ref().unwrap();
+        assert_eq!(first.tags, [("time".into(), "2024-01-01T00:00:00Z".into())]);
+        assert_eq!(first.parameters, ["#irk", "one"]);
+        assert_eq!(msgs[1].as_ref().unwrap().source.as_deref(), Some("b!b@h"));
+        assert_eq!(msgs[2], Err(crate::Error::InvalidCommand));
+    }
+
+    #[test]
+    fn targets_detailed() {
+        let msg = Message::from(":alice PRIVMSG @#a,+#b,rini :hi");
+        let cmd = Command::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        assert!(cmd.targets_detailed("@+").eq([
+            (Some('@'), "#a"),
+            (Some('+'), "#b"),
+            (None, "rini")
+        ]));
+        assert!(cmd
+            .targets_detailed("")
+            .eq([(None, "@#a"), (None, "+#b"), (None, "rini")]));
+
+        let cmd = Command::Nick { nickname: "rini" };
+        assert_eq!(cmd.targets_detailed("@+").next(), None);
+    }
+
+    #[test]
+    fn pong() {
+        let ping = Command::Ping {
+            token: "tok",
+            target: None,
+        };
+        let pong = Serializer::new(ping.pong().unwrap()).unwrap();
+        assert_eq!(pong.to_message().unwrap().to_string(), "PONG :tok");
+
+        let ping = Command::Ping {
+            token: "serverA",
+            target: Some("serverB"),
+        };
+        let pong = Serializer::new(ping.pong().unwrap()).unwrap();
+        assert_eq!(
+            pong.to_message().unwrap().to_string(),
+            "PONG serverB :serverA"
+        );
+
+        assert_eq!(Command::Nick { nickname: "rini" }.pong(), None);
+    }
+
+    test_roundtrip! {
+        privmsg;
+        ":rini!rini@example.com PRIVMSG #irk,alice :hello there" => Command::Privmsg {
+            targets: vec!["#irk", "alice"],
+            text: Trailing("hello there"),
+        },
+        "NOTICE rini word" => Command::Notice {
+            targets: vec!["rini"],
+            text: Trailing("word"),
+        }
+    }
+
+    test_roundtrip! {
+        join;
+        "JOIN #irk" => Command::Join {
+            channels: vec!["#irk"],
+            keys: None,
+        },
+        "JOIN #irk,#secret x,hunter2" => Command::Join {
+            channels: vec!["#irk", "#secret"],
+            keys: Some(vec!["x", "hunter2"]),
+        }
+    }
+
+    #[test]
+    fn part_all() {
+        let msg = Message::from("JOIN 0");
+        let cmd = Command::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        assert!(cmd.is_part_all());
+        assert_eq!(cmd, Command::part_all());
+
+        let ser = Serializer::default()
+            .compact()
+            .argument(Command::part_all())
+            .unwrap();
+        assert_eq!(ser.to_message().unwrap().to_string(), "JOIN 0");
+
+        let msg = Message::from("JOIN 0,#irk");
+        let cmd = Command::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        assert!(!cmd.is_part_all());
+    }
+
+    test_roundtrip! {
+        markread;
+        "MARKREAD #irk timestamp=2024-01-01T00:00:00.000Z" => Command::Markread {
+            target: "#irk",
+            timestamp: Some("2024-01-01T00:00:00.000Z"),
+        },
+        "MARKREAD #irk" => Command::Markread {
+            target: "#irk",
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn markread_unset() {
+        let msg = Message::from(":irc.example.com MARKREAD #irk *");
+        assert_eq!(
+            Command::deserialize(&mut Deserializer::from_message(msg)),
+            Ok(Command::Markread {
+                target: "#irk",
+                timestamp: None,
+            })
+        );
+    }
+
+    test_roundtrip! {
+        part;
+        "PART #irk" => Command::Part {
+            channels: vec!["#irk"],
+            reason: None,
+        },
+        "PART #irk :" => Command::Part {
+            channels: vec!["#irk"],
+            reason: Some(""),
+        },
+        "PART #irk,#rust :bye" => Command::Part {
+            channels: vec!["#irk", "#rust"],
+            reason: Some("bye"),
+        }
+    }
+
+    test_roundtrip! {
+        kick;
+        "KICK #irk alice" => Command::Kick {
+            channels: vec!["#irk"],
+            users: vec!["alice"],
+            reason: None,
+        },
+        "KICK #irk,#rust alice,bob :spamming" => Command::Kick {
+            channels: vec!["#irk", "#rust"],
+            users: vec!["alice", "bob"],
+            reason: Some("spamming"),
+        }
+    }
+
+    test_roundtrip! {
+        away;
+        ":alice!a@example.com AWAY :gone fishing" => Command::Away {
+            message: Some("gone fishing"),
+        },
+        ":alice!a@example.com AWAY" => Command::Away { message: None }
+    }
+
+    test_roundtrip! {
+        invite;
+        ":rini!rini@example.com INVITE alice #irk" => Command::Invite {
+            nickname: "alice",
+            channel: "#irk",
+        }
+    }
+
+    test_roundtrip! {
+        silence;
+        "SILENCE +*!*@spam.example.com" => Command::Silence {
+            mask: Some(Signed { add: true, value: "*!*@spam.example.com" }),
+        },
+        "SILENCE -*!*@spam.example.com" => Command::Silence {
+            mask: Some(Signed { add: false, value: "*!*@spam.example.com" }),
+        },
+        "SILENCE" => Command::Silence { mask: None }
+    }
+
+    test_roundtrip! {
+        trace;
+        "TRACE" => Command::Trace { target: None },
+        "TRACE irc.example.com" => Command::Trace {
+            target: Some("irc.example.com"),
+        }
+    }
+
+    test_roundtrip! {
+        info;
+        "INFO" => Command::Info { target: None },
+        "INFO irc.example.com" => Command::Info {
+            target: Some("irc.example.com"),
+        }
+    }
+
+    test_roundtrip! {
+        help;
+        "HELP" => Command::Help { subject: None },
+        "HELP :privmsg" => Command::Help {
+            subject: Some("privmsg"),
+        }
+    }
+
+    test_roundtrip! {
+        users;
+        "USERS" => Command::Users { target: None },
+        "USERS irc.example.com" => Command::Users {
+            target: Some("irc.example.com"),
+        }
+    }
+
+    test_roundtrip! {
+        stats;
+        "STATS" => Command::Stats { query: None, target: None },
+        "STATS u" => Command::Stats { query: Some('u'), target: None },
+        "STATS l irc.example.com" => Command::Stats {
+            query: Some('l'),
+            target: Some("irc.example.com"),
+        }
+    }
+
+    #[test]
+    fn stats_target_needs_query() {
+        let cmd = Command::Stats {
+            query: None,
+            target: Some("irc.example.com"),
+        };
+        assert!(matches!(
+            Serializer::new(cmd),
+            Err(crate::Error::Serialize(_))
+        ));
+    }
+
+    #[test]
+    fn stats_query_is_one_char() {
+        let msg = Message::from("STATS links");
+        assert!(Command::deserialize(&mut Deserializer::from_message(msg)).is_err());
+    }
+
+    test_roundtrip! {
+        lusers;
+        "LUSERS" => Command::Lusers { mask: None, target: None },
+        "LUSERS *.example.com" => Command::Lusers {
+            mask: Some("*.example.com"),
+            target: None,
+        },
+        "LUSERS *.example.com irc.example.com" => Command::Lusers {
+            mask: Some("*.example.com"),
+            target: Some("irc.example.com"),
+        }
+    }
+
+    #[test]
+    fn lusers_target_needs_mask() {
+        let mask = Some("*.example.com");
+        let target = Some("irc.example.com");
+        for (mask, target) in [(None, None), (mask, None), (mask, target), (None, target)] {
+            let cmd = Command::Lusers { mask, target };
+            let Ok(ser) = Serializer::new(&cmd) else {
+                assert!(mask.is_none() && target.is_some());
+                continue;
+            };
+            let line = ser.to_message().unwrap().to_string();
+            let msg = Message::from(line.as_str());
+            assert_eq!(
+                Command::deserialize(&mut Deserializer::from_message(msg)),
+                Ok(cmd)
+            );
+        }
+    }
+
+    test_roundtrip! {
+        setname;
+        ":rini!rini@example.com SETNAME :rini c." => Command::Setname { realname: "rini c." }
+    }
+
+    test_roundtrip! {
+        chghost;
+        ":alice!old@old.example.com CHGHOST alice new.example.com" => Command::Chghost {
+            username: "alice",
+            host: "new.example.com",
+        }
+    }
+
+    #[test]
+    fn chghost_prefix() {
+        let msg = Message::from(":alice!old@old.example.com CHGHOST ~alice new.example.com");
+        let source = msg.source.unwrap();
+        let cmd = Command::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        assert_eq!(
+            cmd.chghost_prefix(source).as_deref(),
+            Some("alice!~alice@new.example.com")
+        );
+        assert_eq!(Command::Nick { nickname: "a" }.chghost_prefix(source), None);
+    }
+
+    #[test]
+    fn rename_notification() {
+        let msg = Message::from(":irc.example.com RENAME #irk #irk-dev :moving on");
+        let cmd = Command::deserialize(&mut Deserializer::from_message(msg)).unwrap();
+        let Command::Rename { old, new, .. } = cmd else {
+            panic!("not a rename: {cmd:?}");
+        };
+        assert_eq!((old, new), ("#irk", "#irk-dev"));
+
+        let mut channels = HashMap::from([("#irk".to_string(), 42), ("#rust".to_string(), 7)]);
+        assert!(cmd.rename_channel(&mut channels));
+        assert_eq!(channels.get("#irk-dev"), Some(&42));
+        assert_eq!(channels.get("#irk"), None);
+        assert!(!cmd.rename_channel(&mut channels));
+    }
+
+    test_roundtrip! {
+        rename;
+        "RENAME #irk #irk-dev :moving on" => Command::Rename {
+            old: "#irk",
+            new: "#irk-dev",
+            reason: Some("moving on"),
+        },
+        "RENAME #irk #irk-dev" => Command::Rename {
+            old: "#irk",
+            new: "#irk-dev",
+            reason: None,
+        }
+    }
+
+    test_roundtrip! {
+        cap;
+        "CAP REQ :sasl away-notify" => Command::Cap(Cap::Req {
+            caps: "sasl away-notify",
+        }),
+        "CAP END" => Command::Cap(Cap::End)
+    }
+
+    #[cfg(feature = "server")]
+    test_roundtrip! {
+        bans;
+        "KLINE 3600 *@spam.example.com :go away" => Command::Kline {
+            duration: Seconds(std::time::Duration::from_secs(3600)),
+            mask: "*@spam.example.com",
+            reason: Some("go away"),
+        },
+        "KLINE 0 *@spam.example.com" => Command::Kline {
+            duration: Seconds(std::time::Duration::ZERO),
+            mask: "*@spam.example.com",
+            reason: None,
+        },
+        "GLINE 60 *@*.example.net :flooding" => Command::Gline {
+            duration: Seconds(std::time::Duration::from_secs(60)),
+            mask: "*@*.example.net",
+            reason: Some("flooding"),
+        },
+        "KILL spammer :no spam" => Command::Kill {
+            nickname: "spammer",
+            comment: "no spam",
+        }
+    }
+
+    test_roundtrip! {
+        metadata;
+        "METADATA * SET url :https://example.com/rini" => Command::Metadata {
+            target: "*",
+            subcommand: Metadata::Set {
+                key: "url",
+                value: Some("https://example.com/rini"),
+            },
+        },
+        "METADATA #irk SET topic-color" => Command::Metadata {
+            target: "#irk",
+            subcommand: Metadata::Set {
+                key: "topic-color",
+                value: None,
+            },
+        },
+        "METADATA alice GET url" => Command::Metadata {
+            target: "alice",
+            subcommand: Metadata::Get { key: "url" },
+        },
+        "METADATA alice LIST" => Command::Metadata {
+            target: "alice",
+            subcommand: Metadata::List,
+        }
+    }
+
+    test_roundtrip! {
+        register;
+        "REGISTER rini rini@example.com hunter2" => Command::Register {
+            account: "rini",
+            email: "rini@example.com",
+            password: "hunter2",
+        },
+        "REGISTER * * :correct horse" => Command::Register {
+            account: "*",
+            email: "*",
+            password: "correct horse",
+        }
+    }
+
+    test_roundtrip! {
+        verify;
+        "VERIFY rini 39gvcb7p" => Command::Verify {
+            account: "rini",
+            code: "39gvcb7p",
+        },
+        "VERIFY * 39gvcb7p" => Command::Verify {
+            account: "*",
+            code: "39gvcb7p",
+        }
+    }
+}
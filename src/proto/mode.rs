@@ -0,0 +1,167 @@
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ChanModes {
+    pub list: String,
+    pub always: String,
+    pub on_set: String,
+    pub flags: String,
+    pub prefixes: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ModeChange<'a> {
+    pub source: Option<&'a str>,
+    pub add: bool,
+    pub mode: char,
+    pub arg: Option<&'a str>,
+}
+
+impl ChanModes {
+    // from the CHANMODES and PREFIX isupport values, e.g. "beI,k,l,imnst" and "(ov)@+"
+    pub fn parse(chanmodes: &str, prefix: &str) -> Option<Self> {
+        let mut groups = chanmodes.split(',');
+        let mut next = || groups.next().map(String::from);
+        let (list, always, on_set, flags) = (next()?, next()?, next()?, next()?);
+        let prefixes = match prefix.strip_prefix('(') {
+            Some(rest) => rest.split_once(')')?.0.into(),
+            None => String::new(),
+        };
+        Some(Self {
+            list,
+            always,
+            on_set,
+            flags,
+            prefixes,
+        })
+    }
+
+    pub fn takes_arg(&self, add: bool, mode: char) -> bool {
+        self.list.contains(mode)
+            || self.always.contains(mode)
+            || self.prefixes.contains(mode)
+            || (add && self.on_set.contains(mode))
+    }
+}
+
+impl Default for ChanModes {
+    fn default() -> Self {
+        Self::parse("beI,k,l,imnpst", "(ov)@+").unwrap()
+    }
+}
+
+pub fn parse<'a>(modes: &str, args: &[&'a str], chanmodes: &ChanModes) -> Vec<ModeChange<'a>> {
+    let mut args = args.iter().copied();
+    let mut add = true;
+    let mut changes = Vec::new();
+    for mode in modes.chars() {
+        match mode {
+            '+' => add = true,
+            '-' => add = false,
+            mode => changes.push(ModeChange {
+                source: None,
+                add,
+                mode,
+                arg: match chanmodes.takes_arg(add, mode) {
+                    true => args.next(),
+                    false => None,
+                },
+            }),
+        }
+    }
+    changes
+}
+
+// the reverse of `parse`, only writing a sign when it flips
+pub fn compact(changes: &[ModeChange]) -> (String, Vec<String>) {
+    let mut modes = String::new();
+    let mut args = Vec::new();
+    let mut sign = None;
+    for change in changes {
+        if sign != Some(change.add) {
+            modes.push(if change.add { '+' } else { '-' });
+            sign = Some(change.add);
+        }
+        modes.push(change.mode);
+        args.extend(change.arg.map(String::from));
+    }
+    (modes, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn chanmodes() {
+        let modes = ChanModes::parse("beI,k,l,imnst", "(qaohv)~&@%+").unwrap();
+        assert_eq!(modes.prefixes, "qaohv");
+        assert!(modes.takes_arg(true, 'l'));
+        assert!(!modes.takes_arg(false, 'l'));
+        assert!(modes.takes_arg(false, 'k'));
+        assert!(!modes.takes_arg(true, 'm'));
+        assert_eq!(ChanModes::parse("beI,k", "(ov)@+"), None);
+    }
+
+    #[test]
+    fn mode_changes() {
+        let msg = Message::from(":op!u@h MODE #chan +o-v alice bob");
+        assert_eq!(
+            msg.mode_changes(&ChanModes::default()),
+            Some(vec![
+                ModeChange {
+                    source: Some("op!u@h"),
+                    add: true,
+                    mode: 'o',
+                    arg: Some("alice"),
+                },
+                ModeChange {
+                    source: Some("op!u@h"),
+                    add: false,
+                    mode: 'v',
+                    arg: Some("bob"),
+                },
+            ])
+        );
+
+        let msg = Message::from(":op!u@h MODE #chan +lm-l 10");
+        let changes = msg.mode_changes(&ChanModes::default()).unwrap();
+        let changes: Vec<_> = changes.iter().map(|c| (c.add, c.mode, c.arg)).collect();
+        assert_eq!(
+            changes,
+            [
+                (true, 'l', Some("10")),
+                (true, 'm', None),
+                (false, 'l', None)
+            ]
+        );
+
+        assert_eq!(
+            Message::from("NICK rini").mode_changes(&ChanModes::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn compact_changes() {
+        let change = |add, mode, arg| ModeChange {
+            source: None,
+            add,
+            mode,
+            arg: Some(arg),
+        };
+        let changes = [
+            change(true, 'o', "alice"),
+            change(true, 'v', "bob"),
+            change(false, 'b', "*!*@spam"),
+        ];
+
+        let (modes, args) = compact(&changes);
+        assert_eq!(modes, "+ov-b");
+        assert_eq!(args, ["alice", "bob", "*!*@spam"]);
+        assert_eq!(
+            parse(&modes, &["alice", "bob", "*!*@spam"], &ChanModes::default()),
+            changes
+        );
+        assert_eq!(compact(&[]), (String::new(), vec![]));
+    }
+}
@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use serde::{de, Deserialize, Deserializer};
+
+use super::numeric;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Visibility {
+    Public,
+    Secret,
+    Private,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Reply<'a> {
+    pub visibility: Visibility,
+    pub channel: &'a str,
+    pub names: Vec<&'a str>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Accumulator {
+    pending: HashMap<String, Vec<String>>,
+}
+
+impl Accumulator {
+    pub fn feed(&mut self, reply: &numeric::Reply) -> Option<(String, Vec<String>)> {
+        match reply {
+            numeric::Reply::Names(reply) => {
+                let names = self.pending.entry(reply.channel.into()).or_default();
+                names.extend(reply.names.iter().map(|n| n.to_string()));
+                None
+            }
+            numeric::Reply::EndOfNames { channel } => {
+                let names = self.pending.remove(*channel).unwrap_or_default();
+                Some((channel.to_string(), names))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn split_prefix<'a>(name: &'a str, prefixes: &str) -> (&'a str, &'a str) {
+    let nick = name.trim_start_matches(|c| prefixes.contains(c));
+    name.split_at(name.len() - nick.len())
+}
+
+impl<'de> Deserialize<'de> for Visibility {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match <&str>::deserialize(deserializer)? {
+            "=" => Ok(Self::Public),
+            "@" => Ok(Self::Secret),
+            "*" => Ok(Self::Private),
+            v => Err(de::Error::invalid_value(
+                de::Unexpected::Str(v),
+                &"one of `=`, `@` or `*`",
+            )),
+        }
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Reply<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (visibility, channel, names) = <(Visibility, &str, &str)>::deserialize(deserializer)?;
+
+        Ok(Self {
+            visibility,
+            channel,
+            names: names.split(' ').filter(|n| !n.is_empty()).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn names_reply() {
+        let msg = Message::from(":irc.example.com 353 rini @ #irk :@rini +alice bob ~@carol");
+        let Some(Ok(numeric::Reply::Names(reply))) = numeric::Reply::decode(&msg) else {
+            panic!("not a names reply");
+        };
+
+        assert_eq!(
+            reply,
+            Reply {
+                visibility: Visibility::Secret,
+                channel: "#irk",
+                names: vec!["@rini", "+alice", "bob", "~@carol"],
+            }
+        );
+
+        let members: Vec<_> = reply
+            .names
+            .iter()
+            .map(|n| split_prefix(n, "~&@%+"))
+            .collect();
+        assert_eq!(
+            members,
+            [("@", "rini"), ("+", "alice"), ("", "bob"), ("~@", "carol")]
+        );
+
+        let msg = Message::from(":irc.example.com 353 rini ! #irk :rini");
+        assert!(matches!(numeric::Reply::decode(&msg), Some(Err(_))));
+    }
+
+    #[test]
+    fn accumulate() {
+        let mut names = Accumulator::default();
+        let mut done = None;
+        for line in [
+            ":irc.example.com 353 rini = #irk :@rini +alice",
+            ":irc.example.com 353 rini = #rust :ferris",
+            ":irc.example.com 353 rini = #irk :bob",
+            ":irc.example.com 366 rini #irk :End of /NAMES list",
+        ] {
+            let reply = numeric::Reply::decode(&Message::from(line))
+                .unwrap()
+                .unwrap();
+            done = names.feed(&reply);
+        }
+
+        assert_eq!(
+            done,
+            Some((
+                "#irk".into(),
+                vec!["@rini".into(), "+alice".into(), "bob".into()]
+            ))
+        );
+        assert_eq!(names.pending.len(), 1);
+    }
+}
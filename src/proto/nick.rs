@@ -0,0 +1,75 @@
+use super::numeric::Reply;
+
+// RFC 1459 NICKLEN, only the default until the server's ISUPPORT says otherwise
+pub const MAX_LEN: usize = 9;
+
+// `nicklen` is usually `ServerInfo::nicklen`. `None` once the suffix leaves no room for the base
+pub fn fallback(base: &str, attempt: usize, nicklen: usize) -> Option<String> {
+    let suffix = match attempt {
+        0 => String::new(),
+        1 => "_".into(),
+        n => n.to_string(),
+    };
+    let keep = nicklen.checked_sub(suffix.len()).filter(|&keep| keep > 0)?;
+    Some(base.chars().take(keep).collect::<String>() + &suffix)
+}
+
+pub fn in_use<'a>(reply: &Reply<'a>) -> Option<&'a str> {
+    match reply {
+        Reply::NicknameInUse { nickname } => Some(nickname),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn fallbacks() {
+        let nicks: Vec<_> = (0..4)
+            .map(|i| fallback("rini", i, MAX_LEN).unwrap())
+            .collect();
+        assert_eq!(nicks, ["rini", "rini_", "rini2", "rini3"]);
+
+        assert_eq!(
+            fallback("ninechars", 0, MAX_LEN).as_deref(),
+            Some("ninechars")
+        );
+        assert_eq!(
+            fallback("ninechars", 1, MAX_LEN).as_deref(),
+            Some("ninechar_")
+        );
+        assert_eq!(
+            fallback("ninechars", 2, MAX_LEN).as_deref(),
+            Some("ninechar2")
+        );
+        assert_eq!(
+            fallback("ninechars", 12, MAX_LEN).as_deref(),
+            Some("ninecha12")
+        );
+        assert_eq!(
+            fallback("waytoolongnick", 0, MAX_LEN).as_deref(),
+            Some("waytoolon")
+        );
+        assert_eq!(
+            fallback("waytoolongnick", 1, 30).as_deref(),
+            Some("waytoolongnick_")
+        );
+        assert_eq!(
+            fallback("waytoolongnick", 1, 14).as_deref(),
+            Some("waytoolongnic_")
+        );
+        assert_eq!(fallback("rini", 12, 1), None);
+        assert_eq!(fallback("rini", 12, 2), None);
+        assert_eq!(fallback("rini", 12, 3).as_deref(), Some("r12"));
+    }
+
+    #[test]
+    fn nickname_in_use() {
+        let msg = Message::from(":irc.example.com 433 * rini :Nickname is already in use");
+        let reply = Reply::decode(&msg).unwrap().unwrap();
+        assert_eq!(in_use(&reply), Some("rini"));
+    }
+}
@@ -0,0 +1,579 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::{Error, Message, Result};
+
+use super::{names, who, Deserializer, Seconds};
+
+pub fn parse(code: &str) -> Option<u16> {
+    match code.len() == 3 && code.bytes().all(|b| b.is_ascii_digit()) {
+        true => code.parse().ok(),
+        false => None,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NumericCategory {
+    Connection,
+    Reply,
+    Error,
+    Unknown,
+}
+
+pub fn numeric_category(code: u16) -> NumericCategory {
+    match code {
+        1..=99 => NumericCategory::Connection,
+        200..=399 => NumericCategory::Reply,
+        400..=599 => NumericCategory::Error,
+        _ => NumericCategory::Unknown,
+    }
+}
+
+pub fn parse_lenient(code: &str) -> Option<u16> {
+    let code = code.trim_start_matches(' ');
+    match (1..=3).contains(&code.len()) && code.bytes().all(|b| b.is_ascii_digit()) {
+        true => code.parse().ok(),
+        false => None,
+    }
+}
+
+pub fn normalize(code: &str) -> Option<String> {
+    parse_lenient(code).map(|code| format!("{code:03}"))
+}
+
+// pulls the numbers out of replies like "There are 3 users and 1 invisible on 2 servers"
+fn counts<const N: usize>(text: &str) -> Result<[u32; N]> {
+    let mut nums = text.split(' ').filter_map(|w| w.parse().ok());
+    let mut counts = [0; N];
+    for count in &mut counts {
+        *count = nums
+            .next()
+            .ok_or_else(|| Error::Deserialize(format!("expected {N} counts in {text:?}")))?;
+    }
+    Ok(counts)
+}
+
+// skips the target nick all numerics start with
+pub(crate) fn params<'a, T: Deserialize<'a>>(msg: &Message<'a>) -> Result<T> {
+    let params = msg.parameters.get(1..).unwrap_or_default();
+    T::deserialize(&mut Deserializer::from_params(params.to_vec()))
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
+pub struct InviteList<'a> {
+    pub channel: &'a str,
+    pub mask: &'a str,
+    pub set_by: Option<&'a str>,
+    pub set_at: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct UserAddress<'a> {
+    pub nickname: &'a str,
+    pub oper: bool,
+    pub away: bool,
+    pub address: &'a str,
+}
+
+impl<'a> UserAddress<'a> {
+    // nick[*]=<+|->user@host
+    pub fn parse(reply: &'a str) -> Option<Self> {
+        let (nickname, rest) = reply.split_once('=')?;
+        let (nickname, oper) = match nickname.strip_suffix('*') {
+            Some(nickname) => (nickname, true),
+            None => (nickname, false),
+        };
+        let (away, address) = match rest.split_at_checked(1)? {
+            ("+", address) => (false, address),
+            ("-", address) => (true, address),
+            _ => return None,
+        };
+
+        Some(Self {
+            nickname,
+            oper,
+            away,
+            address,
+        })
+    }
+
+    fn parse_list(msg: &Message<'a>) -> Result<Vec<Self>> {
+        let list = msg.parameters.get(1).ok_or(Error::Eof)?;
+        list.split(' ')
+            .filter(|reply| !reply.is_empty())
+            .map(|reply| {
+                Self::parse(reply).ok_or_else(|| {
+                    Error::Deserialize(format!("invalid user address reply {reply:?}"))
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct TopicInfo {
+    pub channel: String,
+    pub text: Option<String>,
+    pub set_by: Option<String>,
+    pub set_at: Option<u64>,
+}
+
+impl TopicInfo {
+    pub fn update(&mut self, reply: &Reply) -> bool {
+        let channel = match reply {
+            Reply::NoTopic { channel }
+            | Reply::Topic { channel, .. }
+            | Reply::TopicWhoTime { channel, .. } => *channel,
+            _ => return false,
+        };
+
+        if self.channel != channel {
+            *self = Self {
+                channel: channel.into(),
+                ..Default::default()
+            };
+        }
+
+        match *reply {
+            Reply::NoTopic { .. } => self.text = None,
+            Reply::Topic { text, .. } => self.text = Some(text.into()),
+            Reply::TopicWhoTime { set_by, set_at, .. } => {
+                self.set_by = Some(set_by.into());
+                self.set_at = Some(set_at);
+            }
+            _ => unreachable!(),
+        }
+
+        true
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Reply<'a> {
+    LuserClient {
+        users: u32,
+        invisible: u32,
+        servers: u32,
+    },
+    LuserOp {
+        operators: u32,
+    },
+    LuserUnknown {
+        connections: u32,
+    },
+    LuserChannels {
+        channels: u32,
+    },
+    LuserMe {
+        clients: u32,
+        servers: u32,
+    },
+    UserHost(Vec<UserAddress<'a>>),
+    UserIp(Vec<UserAddress<'a>>),
+    WhoisActually {
+        nickname: &'a str,
+        ip: &'a str,
+    },
+    WhoisIdle {
+        nickname: &'a str,
+        idle: Duration,
+        signon: u64,
+    },
+    EndOfWhois {
+        nickname: &'a str,
+    },
+    EndOfWho {
+        mask: &'a str,
+    },
+    NoTopic {
+        channel: &'a str,
+    },
+    Topic {
+        channel: &'a str,
+        text: &'a str,
+    },
+    TopicWhoTime {
+        channel: &'a str,
+        set_by: &'a str,
+        set_at: u64,
+    },
+    WhoReply(who::Reply<'a>),
+    Names(names::Reply<'a>),
+    EndOfNames {
+        channel: &'a str,
+    },
+    InviteList(InviteList<'a>),
+    EndOfInviteList {
+        channel: &'a str,
+    },
+    UsersStart,
+    Users {
+        username: &'a str,
+        tty: &'a str,
+        host: &'a str,
+    },
+    EndOfUsers,
+    NoUsers,
+    HostHidden {
+        host: &'a str,
+    },
+    NicknameInUse {
+        nickname: &'a str,
+    },
+    YoureOper,
+    Unknown(u16, Vec<&'a str>),
+}
+
+impl<'a> Reply<'a> {
+    pub fn decode(msg: &Message<'a>) -> Option<Result<Self>> {
+        let code = msg.numeric()?;
+        Some(Self::decode_numeric(code, msg))
+    }
+
+    fn decode_numeric(code: u16, msg: &Message<'a>) -> Result<Self> {
+        Ok(match code {
+            251 => {
+                let (text,) = params(msg)?;
+                let [users, invisible, servers] = counts(text)?;
+                Self::LuserClient {
+                    users,
+                    invisible,
+                    servers,
+                }
+            }
+            252 => {
+                let (operators,) = params(msg)?;
+                Self::LuserOp { operators }
+            }
+            253 => {
+                let (connections,) = params(msg)?;
+                Self::LuserUnknown { connections }
+            }
+            254 => {
+                let (channels,) = params(msg)?;
+                Self::LuserChannels { channels }
+            }
+            255 => {
+                let (text,) = params(msg)?;
+                let [clients, servers] = counts(text)?;
+                Self::LuserMe { clients, servers }
+            }
+            302 => Self::UserHost(UserAddress::parse_list(msg)?),
+            340 => Self::UserIp(UserAddress::parse_list(msg)?),
+            338 => match msg.parameters.as_slice() {
+                [_, nickname, .., ip, _] => Self::WhoisActually { nickname, ip },
+                _ => return Err(Error::Eof),
+            },
+            317 => {
+                let (nickname, Seconds(idle), signon) = params(msg)?;
+                Self::WhoisIdle {
+                    nickname,
+                    idle,
+                    signon,
+                }
+            }
+            318 => {
+                let (nickname,) = params(msg)?;
+                Self::EndOfWhois { nickname }
+            }
+            315 => {
+                let (mask,) = params(msg)?;
+                Self::EndOfWho { mask }
+            }
+            331 => {
+                let (channel,) = params(msg)?;
+                Self::NoTopic { channel }
+            }
+            332 => {
+                let (channel, text) = params(msg)?;
+                Self::Topic { channel, text }
+            }
+            333 => {
+                let (channel, set_by, set_at) = params(msg)?;
+                Self::TopicWhoTime {
+                    channel,
+                    set_by,
+                    set_at,
+                }
+            }
+            352 => Self::WhoReply(params(msg)?),
+            353 => Self::Names(params(msg)?),
+            366 => {
+                let (channel,) = params(msg)?;
+                Self::EndOfNames { channel }
+            }
+            346 => Self::InviteList(params(msg)?),
+            347 => {
+                let (channel,) = params(msg)?;
+                Self::EndOfInviteList { channel }
+            }
+            392 => Self::UsersStart,
+            393 => {
+                let (text,): (&str,) = params(msg)?;
+                match text.split_whitespace().collect::<Vec<_>>()[..] {
+                    [username, tty, host] => Self::Users {
+                        username,
+                        tty,
+                        host,
+                    },
+                    _ => {
+                        return Err(Error::Deserialize(format!(
+                            "invalid users reply {text:?}, expected username, tty and host"
+                        )))
+                    }
+                }
+            }
+            381 => Self::YoureOper,
+            394 => Self::EndOfUsers,
+            395 => Self::NoUsers,
+            396 => {
+                let (host,) = params(msg)?;
+                Self::HostHidden { host }
+            }
+            433 => {
+                let (nickname,) = params(msg)?;
+                Self::NicknameInUse { nickname }
+            }
+            _ => Self::Unknown(code, msg.parameters.clone()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient_codes() {
+        assert_eq!(parse("001"), Some(1));
+        assert_eq!(parse("01"), None);
+        assert_eq!(parse("1"), None);
+
+        assert_eq!(parse_lenient("1"), Some(1));
+        assert_eq!(parse_lenient("01"), Some(1));
+        assert_eq!(parse_lenient("001"), Some(1));
+        assert_eq!(parse_lenient("  01"), Some(1));
+        assert_eq!(parse_lenient("0001"), None);
+        assert_eq!(parse_lenient("PING"), None);
+
+        assert_eq!(normalize("1").as_deref(), Some("001"));
+        assert_eq!(normalize("42").as_deref(), Some("042"));
+        assert_eq!(normalize("0001"), None);
+    }
+
+    #[test]
+    fn categories() {
+        assert_eq!(numeric_category(1), NumericCategory::Connection);
+        assert_eq!(numeric_category(5), NumericCategory::Connection);
+        assert_eq!(numeric_category(353), NumericCategory::Reply);
+        assert_eq!(numeric_category(433), NumericCategory::Error);
+        assert_eq!(numeric_category(599), NumericCategory::Error);
+        assert_eq!(numeric_category(0), NumericCategory::Unknown);
+        assert_eq!(numeric_category(150), NumericCategory::Unknown);
+        assert_eq!(numeric_category(903), NumericCategory::Unknown);
+    }
+
+    #[test]
+    fn invite_list() {
+        let msg =
+            Message::from(":irc.example.com 346 rini #irk *!*@example.com ChanServ 1700000000");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::InviteList(InviteList {
+                channel: "#irk",
+                mask: "*!*@example.com",
+                set_by: Some("ChanServ"),
+                set_at: Some(1700000000),
+            })))
+        );
+
+        let msg = Message::from(":irc.example.com 346 rini #irk *!*@example.com");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::InviteList(InviteList {
+                channel: "#irk",
+                mask: "*!*@example.com",
+                set_by: None,
+                set_at: None,
+            })))
+        );
+
+        let msg = Message::from(":irc.example.com 347 rini #irk :End of Channel Invite List");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::EndOfInviteList { channel: "#irk" }))
+        );
+    }
+
+    #[test]
+    fn not_numeric() {
+        assert_eq!(Reply::decode(&Message::from("NICK rini")), None);
+        assert_eq!(
+            Reply::decode(&Message::from(":irc.example.com 999 rini :what")),
+            Some(Ok(Reply::Unknown(999, vec!["rini", "what"])))
+        );
+    }
+
+    #[test]
+    fn user_addresses() {
+        let msg = Message::from(
+            ":irc.example.com 340 rini :rini*=+rini@192.0.2.1 alice=-alice@2001:db8::1",
+        );
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::UserIp(vec![
+                UserAddress {
+                    nickname: "rini",
+                    oper: true,
+                    away: false,
+                    address: "rini@192.0.2.1",
+                },
+                UserAddress {
+                    nickname: "alice",
+                    oper: false,
+                    away: true,
+                    address: "alice@2001:db8::1",
+                },
+            ])))
+        );
+
+        let msg = Message::from(":irc.example.com 302 rini :alice=+alice@example.com");
+        assert!(matches!(Reply::decode(&msg), Some(Ok(Reply::UserHost(v))) if v.len() == 1));
+
+        let msg = Message::from(":irc.example.com 340 rini :alice");
+        assert!(matches!(
+            Reply::decode(&msg),
+            Some(Err(Error::Deserialize(_)))
+        ));
+    }
+
+    #[test]
+    fn whois_actually() {
+        let msg = Message::from(":irc.example.com 338 rini alice 192.0.2.1 :actually using host");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::WhoisActually {
+                nickname: "alice",
+                ip: "192.0.2.1"
+            }))
+        );
+
+        let msg = Message::from(":irc.example.com 338 rini alice alice@example.com 192.0.2.1 :Actual user@host, Actual IP");
+        assert!(matches!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::WhoisActually {
+                ip: "192.0.2.1",
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn topic_info() {
+        let mut topic = TopicInfo::default();
+        for line in [
+            ":irc.example.com 332 rini #irk :irk development | be nice",
+            ":irc.example.com 333 rini #irk alice!alice@example.com 1700000000",
+        ] {
+            let reply = Reply::decode(&Message::from(line)).unwrap().unwrap();
+            assert!(topic.update(&reply));
+        }
+
+        assert_eq!(
+            topic,
+            TopicInfo {
+                channel: "#irk".into(),
+                text: Some("irk development | be nice".into()),
+                set_by: Some("alice!alice@example.com".into()),
+                set_at: Some(1700000000),
+            }
+        );
+
+        let reply = Reply::decode(&Message::from(
+            ":irc.example.com 331 rini #rust :No topic is set",
+        ));
+        assert!(topic.update(&reply.unwrap().unwrap()));
+        assert_eq!(
+            topic,
+            TopicInfo {
+                channel: "#rust".into(),
+                ..Default::default()
+            }
+        );
+
+        let reply = Reply::decode(&Message::from(":irc.example.com 315 rini #irk :End of WHO"));
+        assert!(!topic.update(&reply.unwrap().unwrap()));
+    }
+
+    #[test]
+    fn lusers() {
+        let msg = Message::from(
+            ":irc.example.com 251 rini :There are 42 users and 7 invisible on 3 servers",
+        );
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::LuserClient {
+                users: 42,
+                invisible: 7,
+                servers: 3
+            }))
+        );
+
+        let msg = Message::from(":irc.example.com 252 rini 5 :operator(s) online");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::LuserOp { operators: 5 }))
+        );
+
+        let msg = Message::from(":irc.example.com 255 rini :I have 12 clients and 1 servers");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::LuserMe {
+                clients: 12,
+                servers: 1
+            }))
+        );
+
+        let msg = Message::from(":irc.example.com 251 rini :There are some users");
+        assert!(matches!(
+            Reply::decode(&msg),
+            Some(Err(Error::Deserialize(_)))
+        ));
+    }
+
+    #[test]
+    fn host_hidden() {
+        let msg = Message::from(
+            ":irc.example.com 396 rini rini.users.example.com :is now your displayed host",
+        );
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::HostHidden {
+                host: "rini.users.example.com"
+            }))
+        );
+    }
+
+    #[test]
+    fn users() {
+        let msg = Message::from(":irc.example.com 393 rini :rini     pts/0     example.com");
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::Users {
+                username: "rini",
+                tty: "pts/0",
+                host: "example.com",
+            }))
+        );
+
+        let msg = Message::from(":irc.example.com 393 rini :rini pts/0");
+        assert!(matches!(
+            Reply::decode(&msg),
+            Some(Err(Error::Deserialize(_)))
+        ));
+
+        let msg = Message::from(":irc.example.com 394 rini :End of users");
+        assert_eq!(Reply::decode(&msg), Some(Ok(Reply::EndOfUsers)));
+    }
+}
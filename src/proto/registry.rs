@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use crate::Message;
+
+type Handler<'h, R> = Box<dyn FnMut(&Message) -> R + 'h>;
+
+pub struct CommandRegistry<'h, R = ()> {
+    handlers: HashMap<String, Handler<'h, R>>,
+    fallback: Option<Handler<'h, R>>,
+}
+
+impl<'h, R> CommandRegistry<'h, R> {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+            fallback: None,
+        }
+    }
+
+    pub fn on(mut self, command: &str, f: impl FnMut(&Message) -> R + 'h) -> Self {
+        self.handlers
+            .insert(command.to_ascii_uppercase(), Box::new(f));
+        self
+    }
+
+    pub fn fallback(mut self, f: impl FnMut(&Message) -> R + 'h) -> Self {
+        self.fallback = Some(Box::new(f));
+        self
+    }
+
+    pub fn dispatch(&mut self, msg: &Message) -> Option<R> {
+        let handler = match self.handlers.get_mut(&msg.command.to_ascii_uppercase()) {
+            Some(handler) => handler,
+            None => self.fallback.as_mut()?,
+        };
+        Some(handler(msg))
+    }
+}
+
+impl<R> Default for CommandRegistry<'_, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> std::fmt::Debug for CommandRegistry<'_, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommandRegistry")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatch() {
+        let mut seen = Vec::new();
+        let mut registry = CommandRegistry::new()
+            .on("privmsg", |msg| {
+                seen.push(msg.parameters.join(" "));
+                "privmsg"
+            })
+            .on("PING", |_| "ping");
+
+        let msg = Message::from(":alice!a@example.com PRIVMSG #irk :hi there");
+        assert_eq!(registry.dispatch(&msg), Some("privmsg"));
+        assert_eq!(registry.dispatch(&Message::from("PING :x")), Some("ping"));
+        assert_eq!(registry.dispatch(&Message::from("NICK rini")), None);
+
+        let mut registry = registry.fallback(|_| "other");
+        assert_eq!(
+            registry.dispatch(&Message::from("NICK rini")),
+            Some("other")
+        );
+
+        drop(registry);
+        assert_eq!(seen, ["#irk hi there"]);
+    }
+}
@@ -0,0 +1,170 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Error, Result};
+
+// longer payloads get split into lines of this many base64 characters
+pub const CHUNK_LEN: usize = 400;
+
+// what goes after AUTHENTICATE once the mechanism is picked, in either direction
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Chunk<'a> {
+    // `+`, an empty challenge or response
+    Empty,
+    // `*`, the client giving up
+    Abort,
+    Data(&'a str),
+}
+
+impl<'a> Chunk<'a> {
+    pub fn as_str(&self) -> &'a str {
+        match *self {
+            Self::Empty => "+",
+            Self::Abort => "*",
+            Self::Data(data) => data,
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Chunk<'a> {
+    fn from(value: &'a str) -> Self {
+        match value {
+            "+" => Self::Empty,
+            "*" => Self::Abort,
+            data => Self::Data(data),
+        }
+    }
+}
+
+impl Serialize for Chunk<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Chunk<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <&str>::deserialize(deserializer).map(Self::from)
+    }
+}
+
+// a chunk shorter than CHUNK_LEN ends the payload, or a `+` after one that's exactly as long
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    buf: String,
+}
+
+impl Reassembler {
+    pub fn feed(&mut self, chunk: Chunk) -> Option<Result<Vec<u8>>> {
+        match chunk {
+            Chunk::Abort => {
+                self.buf.clear();
+                return None;
+            }
+            Chunk::Empty => {}
+            Chunk::Data(data) => {
+                self.buf.push_str(data);
+                if data.len() == CHUNK_LEN {
+                    return None;
+                }
+            }
+        }
+        Some(decode(&std::mem::take(&mut self.buf)))
+    }
+}
+
+pub fn decode(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim_end_matches('=');
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in input.chars() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' => 62,
+            '/' => 63,
+            c => return Err(Error::IllegalChar(c)),
+        };
+        acc = acc << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        proto::{Command, Deserializer},
+        Message,
+    };
+
+    #[test]
+    fn continuation() {
+        let de = |line| {
+            let msg = Message::from(line);
+            Command::deserialize(&mut Deserializer::from_message(msg)).unwrap()
+        };
+
+        let cmd = de("AUTHENTICATE PLAIN");
+        assert_eq!(cmd, Command::Authenticate { mechanism: "PLAIN" });
+        assert_eq!(cmd.sasl_chunk(), Some(Chunk::Data("PLAIN")));
+
+        let cmd = de("AUTHENTICATE +");
+        assert_eq!(cmd, Command::Authenticate { mechanism: "+" });
+        assert_eq!(cmd.sasl_chunk(), Some(Chunk::Empty));
+
+        let cmd = de("AUTHENTICATE cmluaQByaW5pAGh1bnRlcjI=");
+        assert_eq!(
+            cmd.sasl_chunk(),
+            Some(Chunk::Data("cmluaQByaW5pAGh1bnRlcjI="))
+        );
+
+        assert_eq!(de("AUTHENTICATE *").sasl_chunk(), Some(Chunk::Abort));
+        assert_eq!(de("NICK rini").sasl_chunk(), None);
+
+        let cmd = Command::Authenticate { mechanism: "PLAIN" };
+        let ser = crate::Serializer::new(cmd).unwrap();
+        assert_eq!(&*ser.args, ["AUTHENTICATE".into(), "PLAIN".into()]);
+
+        let cmd = Command::AuthenticateChunk {
+            chunk: Chunk::Data("cmluaQ=="),
+        };
+        assert_eq!(cmd.sasl_chunk(), Some(Chunk::Data("cmluaQ==")));
+        let ser = crate::Serializer::new(cmd).unwrap();
+        assert_eq!(&*ser.args, ["AUTHENTICATE".into(), "cmluaQ==".into()]);
+    }
+
+    #[test]
+    fn reassemble() {
+        // "xxx" is "eHh4"
+        let encoded = "eHh4".repeat(101);
+        let mut sasl = Reassembler::default();
+        assert_eq!(sasl.feed(Chunk::Data(&encoded[..CHUNK_LEN])), None);
+        assert_eq!(
+            sasl.feed(Chunk::Data(&encoded[CHUNK_LEN..])),
+            Some(Ok("x".repeat(303).into_bytes()))
+        );
+
+        let encoded = "eHh4".repeat(100);
+        assert_eq!(sasl.feed(Chunk::Data(&encoded)), None);
+        assert_eq!(
+            sasl.feed(Chunk::Empty),
+            Some(Ok("x".repeat(300).into_bytes()))
+        );
+
+        assert_eq!(
+            sasl.feed(Chunk::Data("cmluaQByaW5pAGh1bnRlcjI=")),
+            Some(Ok(b"rini\0rini\0hunter2".to_vec()))
+        );
+        assert_eq!(
+            sasl.feed(Chunk::Data("no!")),
+            Some(Err(Error::IllegalChar('!')))
+        );
+    }
+}
@@ -1,20 +1,42 @@
-use serde::{ser, Serialize};
+use std::{borrow::Cow, sync::Arc};
+
+use serde::{de::DeserializeOwned, ser, Serialize};
 
 use crate::{Error, Message, Result};
 
-#[derive(Debug, Default)]
+type Transform = Arc<dyn Fn(&str) -> Cow<str> + Send + Sync>;
+
+#[derive(Default)]
 pub struct Serializer {
     pub args: Vec<Box<str>>,
+    transform: Option<Transform>,
+    checked: bool,
+    compact: bool,
+    trailing: bool,
+    separator: Option<char>,
 }
 
 #[derive(Debug)]
-pub struct Sequence<'a>(&'a mut Serializer, Vec<Box<str>>);
+pub struct Sequence<'a>(&'a mut Serializer, Vec<Box<str>>, char);
 
 impl Serializer {
     pub fn new<T: Serialize>(value: T) -> Result<Self> {
-        let mut ser = Self::default();
-        value.serialize(&mut ser)?;
-        Ok(ser)
+        Self::default().argument(value)
+    }
+
+    pub fn with_transform(mut self, f: impl Fn(&str) -> Cow<str> + Send + Sync + 'static) -> Self {
+        self.transform = Some(Arc::new(f));
+        self
+    }
+
+    pub fn checked(mut self) -> Self {
+        self.checked = true;
+        self
+    }
+
+    pub fn compact(mut self) -> Self {
+        self.compact = true;
+        self
     }
 
     pub fn argument<T: Serialize>(mut self, value: T) -> Result<Self> {
@@ -22,20 +44,95 @@ impl Serializer {
         Ok(self)
     }
 
-    pub fn to_message(&self) -> Result<Message> {
+    // arguments can come one at a time, the command is only done once it's turned into a message
+    pub fn to_message(&self) -> Result<Message<'_>> {
         let (command, param) = self.args.split_first().ok_or(Error::Eof)?;
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            command = command.as_ref(),
+            parameters = param.len(),
+            "serialized command"
+        );
+
         Ok(Message {
+            tags: Vec::new(),
             source: None,
             command,
             parameters: param.iter().map(|c| c.as_ref()).collect(),
+            trailing: !self.compact || self.trailing,
+            raw: "",
         })
     }
+
+    // the transform only sees parameters, not commands and subcommands
+    fn push(&mut self, arg: &str) {
+        match self.transform.clone() {
+            Some(f) => self.push_raw(&f(arg)),
+            None => self.push_raw(arg),
+        }
+    }
+
+    fn push_raw(&mut self, arg: &str) {
+        self.args.push(arg.into());
+        self.trailing = false;
+    }
+}
+
+impl std::fmt::Debug for Serializer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Serializer")
+            .field("args", &self.args)
+            .field("transform", &self.transform.is_some())
+            .field("checked", &self.checked)
+            .field("compact", &self.compact)
+            .field("trailing", &self.trailing)
+            .field("separator", &self.separator)
+            .finish()
+    }
+}
+
+// the other half of `de::greedy`, since `STATS [<query> [<target>]]` has no way to give a target
+// without the query before it
+pub fn greedy<S, A, B>(
+    first: &Option<A>,
+    second: &Option<B>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: ser::Serializer,
+    A: Serialize,
+    B: Serialize,
+{
+    if first.is_none() && second.is_some() {
+        return Err(ser::Error::custom(
+            "optional parameter given without the one before it",
+        ));
+    }
+    (first, second).serialize(serializer)
+}
+
+pub fn serialize_checked<T>(value: &T) -> Result<Serializer>
+where
+    T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    let ser = Serializer::default().checked().argument(value)?;
+
+    #[cfg(debug_assertions)]
+    {
+        let line = ser.to_message()?.to_string();
+        let msg = Message::from(line.as_str());
+        let value2 = T::deserialize(&mut super::Deserializer::from_message(msg))?;
+        assert_eq!(value, &value2, "{line:?} does not deserialize back");
+    }
+
+    Ok(ser)
 }
 
 macro_rules! pushes {
     ($($fun:ident($($param:ident: $type:ty),*) { $val:expr })*) => {
         $(fn $fun(self, $($param: $type),*) -> Result<()> {
-            self.args.push($val.into());
+            self.push(&$val);
             Ok(())
         })*
     };
@@ -79,16 +176,13 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeTuple = Self;
     type SerializeTupleStruct = Self;
     type SerializeTupleVariant = Self;
-    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeMap = Self;
     type SerializeStruct = Self;
     type SerializeStructVariant = Self;
 
     pushes! {
         serialize_str(v: &str) { v }
         serialize_bytes(v: &[u8]) { std::str::from_utf8(v).unwrap() }
-        serialize_unit_variant(_name: &'static str, _index: u32, variant: &'static str) {
-            variant
-        }
     }
 
     pushes_string! {
@@ -102,7 +196,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     forwards_self! {
-        serialize_some() serialize_newtype_struct(&'static str)
+        serialize_some()
     }
 
     serializes_self! {
@@ -110,12 +204,39 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         serialize_struct(&'static str, usize)
     }
 
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.push_raw(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.separator = match name {
+            super::types::SPACE_SEPARATED => Some(' '),
+            super::types::NULL_SEPARATED => Some('\0'),
+            _ => None,
+        };
+        value.serialize(&mut *self)?;
+        if name == super::types::TRAILING {
+            self.trailing = true;
+        }
+        Ok(())
+    }
+
     fn serialize_seq(self, _len: Option<usize>) -> Result<Sequence<'a>> {
-        Ok(Sequence(self, Vec::new()))
+        let separator = self.separator.take().unwrap_or(',');
+        Ok(Sequence(self, Vec::new(), separator))
     }
 
-    fn serialize_map(self, _len: Option<usize>) -> Result<ser::Impossible<(), Error>> {
-        Err(Error::UnsupportedType)
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self> {
+        Ok(self)
     }
 
     fn serialize_newtype_variant<T>(
@@ -128,7 +249,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     where
         T: Serialize + ?Sized,
     {
-        self.args.push(variant.into());
+        self.push_raw(variant);
         value.serialize(self)
     }
 
@@ -139,7 +260,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self> {
-        self.args.push(variant.into());
+        self.push_raw(variant);
         Ok(self)
     }
 
@@ -150,7 +271,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         variant: &'static str,
         _len: usize,
     ) -> Result<Self> {
-        self.args.push(variant.into());
+        self.push_raw(variant);
         Ok(self)
     }
 }
@@ -160,15 +281,47 @@ impl<'a> ser::SerializeSeq for Sequence<'a> {
     type Error = Error;
 
     fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
-        let mut ser = Serializer::default();
+        // each element gets transformed on its own, the joined list doesn't again
+        let mut ser = Serializer {
+            transform: self.0.transform.clone(),
+            ..Default::default()
+        };
         value.serialize(&mut ser)?;
+
+        if self.0.checked {
+            if let Some(c) = ser
+                .args
+                .iter()
+                .find_map(|arg| arg.chars().find(|c| [self.2, ' '].contains(c)))
+            {
+                return Err(Error::IllegalChar(c));
+            }
+        }
+
         self.1.extend(ser.args);
+        Ok(())
+    }
+
+    fn end(self) -> Result<()> {
+        self.0.push_raw(&self.1.join(&self.2.to_string()));
+        Ok(())
+    }
+}
+
+// keys are dropped, which is what makes #[serde(flatten)] spread into parameters
+impl ser::SerializeMap for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
 
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, _key: &T) -> Result<()> {
         Ok(())
     }
 
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
     fn end(self) -> Result<()> {
-        self.0.args.push(self.1.join(",").into());
         Ok(())
     }
 }
@@ -200,3 +353,209 @@ serialize_fields! {
     SerializeStruct::serialize_field(&'static str)
     SerializeStructVariant::serialize_field(&'static str)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::{
+        proto::{CommaSeparated, NullSeparated, SpaceSeparated, Trailing},
+        Command,
+    };
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Note {
+        command: String,
+        target: String,
+        text: String,
+    }
+
+    #[test]
+    fn transform_channels() {
+        let ser = Serializer::default()
+            .with_transform(|arg| match arg.starts_with('#') {
+                true => Cow::Owned(arg.to_uppercase()),
+                false => Cow::Borrowed(arg),
+            })
+            .argument(Command::Topic {
+                channel: "#irk",
+                topic: Some("hello #world"),
+            })
+            .unwrap();
+
+        assert_eq!(
+            ser.to_message().unwrap().to_string(),
+            "TOPIC #IRK :hello #world"
+        );
+    }
+
+    #[test]
+    fn transform_targets() {
+        let ser = Serializer::default()
+            .with_transform(|arg| match arg.starts_with('#') {
+                true => Cow::Owned(format!("net/{arg}")),
+                false => Cow::Borrowed(arg),
+            })
+            .argument(Command::Privmsg {
+                targets: vec!["#a", "#b", "rini"],
+                text: Trailing("hi"),
+            })
+            .unwrap();
+        assert_eq!(
+            ser.to_message().unwrap().to_string(),
+            "PRIVMSG net/#a,net/#b,rini :hi"
+        );
+
+        let ser = Serializer::default()
+            .with_transform(|arg| Cow::Owned(format!("net/{arg}")))
+            .argument(Command::Part {
+                channels: vec!["#a"],
+                reason: None,
+            })
+            .unwrap();
+        assert_eq!(&*ser.args, ["PART".into(), "net/#a".into()]);
+
+        // serializers get handed to other threads, transform or not
+        fn send<T: Send + Sync>(_: &T) {}
+        send(&ser);
+    }
+
+    #[test]
+    fn checked() {
+        let note = Note {
+            command: "NOTE".into(),
+            target: "rini".into(),
+            text: "hello world".into(),
+        };
+        let ser = serialize_checked(&note).unwrap();
+        assert_eq!(
+            ser.to_message().unwrap().to_string(),
+            "NOTE rini :hello world"
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic = "does not deserialize back"]
+    fn checked_asymmetric() {
+        let note = Note {
+            command: "NOTE".into(),
+            target: "rini alice".into(),
+            text: "hello".into(),
+        };
+        let _ = serialize_checked(&note);
+    }
+
+    #[test]
+    fn flatten() {
+        #[derive(Serialize)]
+        struct Target<'a> {
+            channel: &'a str,
+            nickname: &'a str,
+        }
+
+        #[derive(Serialize)]
+        #[serde(rename_all = "UPPERCASE")]
+        enum Flat<'a> {
+            Kick {
+                #[serde(flatten)]
+                target: Target<'a>,
+                reason: Option<&'a str>,
+            },
+        }
+
+        let ser = Serializer::new(Flat::Kick {
+            target: Target {
+                channel: "#irk",
+                nickname: "alice",
+            },
+            reason: Some("bye"),
+        })
+        .unwrap();
+
+        assert_eq!(&*ser.args, ["KICK", "#irk", "alice", "bye"].map(Into::into));
+    }
+
+    #[test]
+    fn compact() {
+        let ser = Serializer::default()
+            .compact()
+            .argument(Command::Privmsg {
+                targets: vec!["#x"],
+                text: Trailing("word"),
+            })
+            .unwrap();
+        assert_eq!(ser.to_message().unwrap().to_string(), "PRIVMSG #x :word");
+
+        let ser = Serializer::default()
+            .compact()
+            .argument(Command::Nick { nickname: "rini" })
+            .unwrap();
+        assert_eq!(ser.to_message().unwrap().to_string(), "NICK rini");
+
+        let ser = Serializer::new(Command::Nick { nickname: "rini" }).unwrap();
+        assert_eq!(ser.to_message().unwrap().to_string(), "NICK :rini");
+    }
+
+    #[test]
+    fn checked_sequence() {
+        let kick = Command::Kick {
+            channels: vec!["#irk"],
+            users: vec!["alice", "bob,carol"],
+            reason: None,
+        };
+
+        assert!(Serializer::new(&kick).is_ok());
+        assert_eq!(
+            Serializer::default().checked().argument(&kick).unwrap_err(),
+            Error::IllegalChar(',')
+        );
+    }
+
+    #[test]
+    fn checked_join_keys() {
+        let join = |key| {
+            let join = Command::Join {
+                channels: vec!["#irk"],
+                keys: Some(vec![key]),
+            };
+            Serializer::default()
+                .checked()
+                .argument(join)
+                .map(|s| s.args)
+        };
+
+        assert_eq!(
+            &*join("hunter2").unwrap(),
+            ["JOIN", "#irk", "hunter2"].map(Into::into)
+        );
+        assert_eq!(join("hunter 2"), Err(Error::IllegalChar(' ')));
+        assert_eq!(join("hunter,2"), Err(Error::IllegalChar(',')));
+    }
+
+    #[test]
+    fn separators() {
+        fn ser(value: impl Serialize) -> Vec<Box<str>> {
+            Serializer::new(value).unwrap().args
+        }
+
+        let fields = vec!["a", "b", "c"];
+
+        assert_eq!(&*ser(&fields), ["a,b,c".into()]);
+        assert_eq!(&*ser(CommaSeparated(fields.clone())), ["a,b,c".into()]);
+        assert_eq!(&*ser(SpaceSeparated(fields.clone())), ["a b c".into()]);
+        assert_eq!(&*ser(NullSeparated(fields)), ["a\0b\0c".into()]);
+
+        // only the outer list takes the separator
+        let nested = SpaceSeparated(vec![vec!["a", "b"], vec!["c"]]);
+        assert_eq!(&*ser(nested), ["a,b c".into()]);
+
+        let checked = Serializer::default()
+            .checked()
+            .argument(NullSeparated(vec!["a", "b\0c"]));
+        assert_eq!(checked.unwrap_err(), Error::IllegalChar('\0'));
+    }
+}
@@ -0,0 +1,75 @@
+use super::numeric::Reply;
+use crate::Message;
+
+// what the server has told us about our own connection so far
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct State {
+    nickname: Option<String>,
+    oper: bool,
+}
+
+impl State {
+    pub fn nickname(&self) -> Option<&str> {
+        self.nickname.as_deref()
+    }
+
+    pub fn is_registered(&self) -> bool {
+        self.nickname.is_some()
+    }
+
+    pub fn is_oper(&self) -> bool {
+        self.oper
+    }
+
+    pub fn feed(&mut self, msg: &Message) {
+        if let Some(nickname) = msg.welcome_nick() {
+            self.nickname = Some(nickname.into());
+            return;
+        }
+        if let Some(Ok(Reply::YoureOper)) = Reply::decode(msg) {
+            self.oper = true;
+            return;
+        }
+
+        // our own user modes, `MODE rini :+o` after OPER or `-o` when it's taken away
+        match (msg.command, msg.parameters.as_slice()) {
+            ("MODE", [target, modes, ..]) if self.nickname.as_deref() == Some(*target) => {
+                let mut add = true;
+                for c in modes.chars() {
+                    match c {
+                        '+' => add = true,
+                        '-' => add = false,
+                        'o' => self.oper = add,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oper() {
+        let mut state = State::default();
+        state.feed(&Message::from(":irc.example.com 001 rini :Welcome"));
+        assert!(state.is_registered());
+        assert!(!state.is_oper());
+
+        state.feed(&Message::from(
+            ":irc.example.com 381 rini :You are now an IRC operator",
+        ));
+        assert!(state.is_oper());
+
+        state.feed(&Message::from(":rini MODE rini :-o"));
+        assert!(!state.is_oper());
+        state.feed(&Message::from(":rini MODE rini :+iwo"));
+        assert!(state.is_oper());
+        state.feed(&Message::from(":alice MODE alice :-o"));
+        assert!(state.is_oper());
+    }
+}
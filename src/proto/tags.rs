@@ -0,0 +1,202 @@
+use std::borrow::Cow;
+
+use crate::{Error, Result};
+
+// `[+][vendor/]name`, where the vendor is a hostname
+pub fn check_key(key: &str) -> Result<()> {
+    let key = key.strip_prefix('+').unwrap_or(key);
+    let (vendor, name) = key.rsplit_once('/').unwrap_or(("", key));
+    let vendor_ok = vendor
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-.".contains(c));
+    let name_ok = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    match !name.is_empty() && vendor_ok && name_ok && !key.starts_with('/') {
+        true => Ok(()),
+        false => Err(Error::InvalidTagKey),
+    }
+}
+
+pub fn escape(value: &str) -> Cow<'_, str> {
+    if !value.contains([';', ' ', '\\', '\r', '\n']) {
+        return Cow::Borrowed(value);
+    }
+
+    let mut out = String::with_capacity(value.len() + 4);
+    for c in value.chars() {
+        match c {
+            ';' => out.push_str("\\:"),
+            ' ' => out.push_str("\\s"),
+            '\\' => out.push_str("\\\\"),
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+// servers only take 4094 bytes of client tags, including the `@` and the space after
+pub const CLIENT_MAX: usize = 4094;
+
+pub fn section_len<'a>(tags: impl IntoIterator<Item = (&'a str, &'a str)>) -> usize {
+    let len: usize = (tags.into_iter())
+        .map(|(key, value)| {
+            // each escape turns one character into two
+            let value = value.len() + value.matches([';', ' ', '\\', '\r', '\n']).count();
+            1 + key.len() + if value == 0 { 0 } else { 1 + value }
+        })
+        .sum();
+    if len == 0 {
+        0
+    } else {
+        len + 1
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum UnknownEscape {
+    // what the spec says, `\q` is `q`
+    #[default]
+    DropBackslash,
+    KeepBackslash,
+}
+
+pub fn unescape(value: &str) -> Cow<'_, str> {
+    unescape_with(value, UnknownEscape::default())
+}
+
+pub fn unescape_with(value: &str, unknown: UnknownEscape) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
+
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(':') => out.push(';'),
+                Some('s') => out.push(' '),
+                Some('r') => out.push('\r'),
+                Some('n') => out.push('\n'),
+                Some('\\') => out.push('\\'),
+                Some(c) => {
+                    if unknown == UnknownEscape::KeepBackslash {
+                        out.push('\\');
+                    }
+                    out.push(c);
+                }
+                None => {}
+            },
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[derive(Debug, Clone)]
+pub struct TagIter<'a> {
+    section: &'a str,
+}
+
+impl<'a> TagIter<'a> {
+    pub fn new(section: &'a str) -> Self {
+        Self { section }
+    }
+
+    pub fn from_line(line: &'a str) -> Self {
+        let section = match line.strip_prefix('@') {
+            Some(rest) => rest.split_once(' ').map_or(rest, |(tags, _)| tags),
+            None => "",
+        };
+        Self::new(section)
+    }
+
+    pub fn get(mut self, key: &str) -> Option<Cow<'a, str>> {
+        self.find_map(|(k, v)| (k == key).then_some(v))
+    }
+}
+
+impl<'a> Iterator for TagIter<'a> {
+    type Item = (&'a str, Cow<'a, str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.section.is_empty() {
+                return None;
+            }
+            let (tag, rest) = self.section.split_once(';').unwrap_or((self.section, ""));
+            self.section = rest;
+            if !tag.is_empty() {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                return Some((key, unescape(value)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaping() {
+        assert!(matches!(escape("plain"), Cow::Borrowed("plain")));
+        assert_eq!(escape("a; b\\c\r\n"), "a\\:\\sb\\\\c\\r\\n");
+
+        assert!(matches!(unescape("plain"), Cow::Borrowed("plain")));
+        assert_eq!(unescape("a\\:\\sb\\\\c\\r\\n"), "a; b\\c\r\n");
+        assert_eq!(unescape("\\b\\"), "b");
+    }
+
+    #[test]
+    fn keys() {
+        for key in ["time", "+typing", "draft/label", "+example.com/emoji-2"] {
+            assert_eq!(check_key(key), Ok(()), "{key}");
+        }
+        for key in ["", "+", "a b", "a=b", "a;b", "/a", "a/", "++a", "a/b/c"] {
+            assert_eq!(check_key(key), Err(Error::InvalidTagKey), "{key}");
+        }
+    }
+
+    #[test]
+    fn unknown_escapes() {
+        assert_eq!(unescape("a\\qb"), "aqb");
+        assert_eq!(
+            unescape_with("a\\qb", UnknownEscape::KeepBackslash),
+            "a\\qb"
+        );
+        assert_eq!(
+            unescape_with("a\\\\b\\s", UnknownEscape::KeepBackslash),
+            "a\\b "
+        );
+    }
+
+    #[test]
+    fn section_size() {
+        let tags = [("+draft/reply", "abc"), ("+note", "a; b"), ("+typing", "")];
+        let section = "@+draft/reply=abc;+note=a\\:\\sb;+typing ";
+        assert_eq!(section_len(tags), section.len());
+        assert_eq!(section_len([]), 0);
+    }
+
+    #[test]
+    fn tag_iter() {
+        let line = "@time=2024-01-01T00:00:00Z;;+draft/reply=abc;msg\\sid;note=a\\sb :n!u@h PRIVMSG #x :hi";
+        let tags: Vec<_> = TagIter::from_line(line).collect();
+        assert_eq!(
+            tags,
+            [
+                ("time", Cow::Borrowed("2024-01-01T00:00:00Z")),
+                ("+draft/reply", Cow::Borrowed("abc")),
+                ("msg\\sid", Cow::Borrowed("")),
+                ("note", Cow::Owned("a b".into())),
+            ]
+        );
+
+        assert_eq!(TagIter::from_line(line).get("note").as_deref(), Some("a b"));
+        assert_eq!(TagIter::from_line(line).get("msgid"), None);
+        assert_eq!(TagIter::from_line("PING :x").next(), None);
+        assert_eq!(TagIter::from_line("@a=b").get("a").as_deref(), Some("b"));
+    }
+}
@@ -0,0 +1,399 @@
+use std::{borrow::Cow, collections::BTreeMap, marker::PhantomData, time::Duration};
+
+use serde::{
+    de::{self, IgnoredAny},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+pub(crate) const TRAILING: &str = "$irk::Trailing";
+pub(crate) const RAW_TAIL: &str = "$irk::RawTail";
+pub(crate) const COMMA_SEPARATED: &str = "$irk::CommaSeparated";
+pub(crate) const SPACE_SEPARATED: &str = "$irk::SpaceSeparated";
+pub(crate) const NULL_SEPARATED: &str = "$irk::NullSeparated";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Trailing<T>(pub T);
+
+impl<T: Serialize> Serialize for Trailing<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(TRAILING, &self.0)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Trailing<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Signed<'a> {
+    pub add: bool,
+    pub value: &'a str,
+}
+
+impl Serialize for Signed<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let sign = if self.add { '+' } else { '-' };
+        serializer.collect_str(&format_args!("{sign}{}", self.value))
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Signed<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <&str>::deserialize(deserializer)?;
+        Ok(match value.strip_prefix('-') {
+            Some(value) => Self { add: false, value },
+            None => Self {
+                add: true,
+                value: value.strip_prefix('+').unwrap_or(value),
+            },
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Placeholder;
+
+impl Serialize for Placeholder {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("*")
+    }
+}
+
+impl<'de> Deserialize<'de> for Placeholder {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IgnoredAny::deserialize(deserializer).map(|_| Self)
+    }
+}
+
+// everything left in the message, with parameters joined back by spaces
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct RawTail<'a>(pub Cow<'a, str>);
+
+impl Serialize for RawTail<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(TRAILING, &self.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawTail<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = RawTail<'de>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "the remaining parameters")
+            }
+
+            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(RawTail(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(RawTail(Cow::Owned(v.into())))
+            }
+
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+                Ok(RawTail(Cow::Owned(v)))
+            }
+
+            fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_str(self)
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(RAW_TAIL, Visitor)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PercentDecoded<'a>(pub Cow<'a, str>);
+
+impl Serialize for PercentDecoded<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = String::with_capacity(self.0.len());
+        for b in self.0.bytes() {
+            match b {
+                b'%' => out.push_str("%25"),
+                b if b.is_ascii_graphic() => out.push(b as char),
+                b => out.push_str(&format!("%{b:02X}")),
+            }
+        }
+        serializer.serialize_str(&out)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for PercentDecoded<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <&str>::deserialize(deserializer)?;
+        if !value.contains('%') {
+            return Ok(Self(Cow::Borrowed(value)));
+        }
+
+        let mut out = Vec::with_capacity(value.len());
+        let mut rest = value.as_bytes();
+        while let Some((&b, tail)) = rest.split_first() {
+            let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+            match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                Some(byte) if b == b'%' => {
+                    out.push(byte);
+                    rest = &tail[2..];
+                }
+                _ => {
+                    out.push(b);
+                    rest = tail;
+                }
+            }
+        }
+        String::from_utf8(out)
+            .map(|s| Self(Cow::Owned(s)))
+            .map_err(de::Error::custom)
+    }
+}
+
+macro_rules! separated {
+    ($($name:ident($tag:ident, $expecting:literal))*) => {
+        $(#[derive(Debug, PartialEq, Eq, Clone)]
+        pub struct $name<T>(pub Vec<T>);
+
+        impl<T: Serialize> Serialize for $name<T> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_newtype_struct($tag, &self.0)
+            }
+        }
+
+        impl<'de, T: Deserialize<'de>> Deserialize<'de> for $name<T> {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                struct Visitor<T>(PhantomData<T>);
+
+                impl<'de, T: Deserialize<'de>> de::Visitor<'de> for Visitor<T> {
+                    type Value = $name<T>;
+
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(f, $expecting)
+                    }
+
+                    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    where
+                        A: de::SeqAccess<'de>,
+                    {
+                        let mut fields = Vec::new();
+                        while let Some(field) = seq.next_element()? {
+                            fields.push(field);
+                        }
+                        Ok($name(fields))
+                    }
+
+                    fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+                    where
+                        D: Deserializer<'de>,
+                    {
+                        Vec::deserialize(deserializer).map($name)
+                    }
+                }
+
+                deserializer.deserialize_newtype_struct($tag, Visitor(PhantomData))
+            }
+        })*
+    };
+}
+
+separated! {
+    // what a plain `Vec` does already, for when the separator should be spelled out
+    CommaSeparated(COMMA_SEPARATED, "comma-separated fields")
+    SpaceSeparated(SPACE_SEPARATED, "space-separated fields")
+    // split on `\0` and keeping empty fields, as in SASL PLAIN payloads
+    NullSeparated(NULL_SEPARATED, "null-separated fields")
+}
+
+// for types that only know how to `FromStr` and `Display` themselves
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Parsed<T>(pub T);
+
+impl<T: std::fmt::Display> Serialize for Parsed<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Parsed<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = <&str>::deserialize(deserializer)?;
+        value.parse().map(Self).map_err(de::Error::custom)
+    }
+}
+
+pub type IndexedParams<'a> = BTreeMap<usize, &'a str>;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct Seconds(pub Duration);
+
+impl Serialize for Seconds {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0.as_secs())
+    }
+}
+
+impl<'de> Deserialize<'de> for Seconds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(|secs| Self(Duration::from_secs(secs)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proto, Message};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "UPPERCASE")]
+    enum Login<'a> {
+        User {
+            username: &'a str,
+            mode: u8,
+            unused: Placeholder,
+            realname: &'a str,
+        },
+    }
+
+    #[test]
+    fn placeholder() {
+        let user = Login::User {
+            username: "rini",
+            mode: 0,
+            unused: Placeholder,
+            realname: "rini c",
+        };
+        let ser = proto::Serializer::new(&user).unwrap();
+        assert_eq!(
+            ser.to_message().unwrap().to_string(),
+            "USER rini 0 * :rini c"
+        );
+
+        let msg = Message::from("USER rini 0 ignored :rini c");
+        let de = Login::deserialize(&mut proto::Deserializer::from_message(msg));
+        assert_eq!(de, Ok(user));
+    }
+
+    #[test]
+    fn raw_tail() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Who<'a> {
+            command: &'a str,
+            mask: &'a str,
+            #[serde(borrow)]
+            rest: RawTail<'a>,
+        }
+
+        let msg = Message::from("WHO #irk %tnuhaf,42 :extra stuff");
+        let who = Who::deserialize(&mut proto::Deserializer::from_message(msg)).unwrap();
+        assert_eq!(who.mask, "#irk");
+        assert_eq!(
+            who.rest,
+            RawTail(Cow::Owned("%tnuhaf,42 extra stuff".into()))
+        );
+
+        let msg = Message::from("WHO #irk :o");
+        let who = Who::deserialize(&mut proto::Deserializer::from_message(msg)).unwrap();
+        assert!(matches!(who.rest, RawTail(Cow::Borrowed("o"))));
+    }
+
+    #[test]
+    fn percent_decoded() {
+        let de =
+            |value| PercentDecoded::deserialize(&mut proto::Deserializer::from_params(vec![value]));
+
+        let decoded = de("caf%C3%A9%20au%20lait%zz").unwrap();
+        assert_eq!(
+            decoded,
+            PercentDecoded(Cow::Owned("café au lait%zz".into()))
+        );
+        assert!(matches!(
+            de("plain"),
+            Ok(PercentDecoded(Cow::Borrowed("plain")))
+        ));
+        assert!(de("%FF").is_err());
+
+        let ser = proto::Serializer::new(PercentDecoded("50% off".into())).unwrap();
+        assert_eq!(&*ser.args, ["50%25%20off".into()]);
+    }
+
+    #[test]
+    fn null_separated() {
+        let de = |value| {
+            NullSeparated::<&str>::deserialize(&mut proto::Deserializer::from_params(vec![value]))
+        };
+        assert_eq!(
+            de("authzid\0authcid\0passwd"),
+            Ok(NullSeparated(vec!["authzid", "authcid", "passwd"]))
+        );
+        assert_eq!(
+            de("\0authcid\0passwd"),
+            Ok(NullSeparated(vec!["", "authcid", "passwd"]))
+        );
+
+        let ser = proto::Serializer::new(NullSeparated(vec!["", "rini", "hunter2"])).unwrap();
+        assert_eq!(&*ser.args, ["\0rini\0hunter2".into()]);
+    }
+
+    #[test]
+    fn space_separated() {
+        let mut de = proto::Deserializer::from_params(vec!["a b  c "]);
+        assert_eq!(
+            SpaceSeparated::<&str>::deserialize(&mut de),
+            Ok(SpaceSeparated(vec!["a", "b", "c"]))
+        );
+
+        let mut de = proto::Deserializer::from_params(vec!["a,b"]);
+        assert_eq!(
+            CommaSeparated::<&str>::deserialize(&mut de),
+            Ok(CommaSeparated(vec!["a", "b"]))
+        );
+    }
+
+    #[test]
+    fn parsed() {
+        #[derive(Debug, PartialEq)]
+        struct Level(u8);
+
+        impl std::str::FromStr for Level {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, String> {
+                match s.strip_prefix("lvl") {
+                    Some(n) => n.parse().map(Level).map_err(|e| format!("{e}")),
+                    None => Err(format!("not a level: {s:?}")),
+                }
+            }
+        }
+
+        let de = |value| {
+            Parsed::<Level>::deserialize(&mut proto::Deserializer::from_params(vec![value]))
+        };
+        assert_eq!(de("lvl3"), Ok(Parsed(Level(3))));
+        assert_eq!(
+            de("3"),
+            Err(crate::Error::Deserialize("not a level: \"3\"".into()))
+        );
+
+        let ser = proto::Serializer::new(Parsed(std::net::Ipv4Addr::LOCALHOST)).unwrap();
+        assert_eq!(&*ser.args, ["127.0.0.1".into()]);
+    }
+
+    #[test]
+    fn seconds() {
+        let de = |value| Seconds::deserialize(&mut proto::Deserializer::from_params(vec![value]));
+        assert_eq!(de("3600"), Ok(Seconds(Duration::from_secs(60 * 60))));
+        assert!(de("an hour").is_err());
+    }
+}
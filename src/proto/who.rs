@@ -0,0 +1,110 @@
+use serde::{de, Deserialize, Deserializer};
+
+// `H`/`G` for here/gone, then `*` for opers, channel status prefixes and `B` for bots
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+pub struct WhoFlags<'a>(pub &'a str);
+
+impl WhoFlags<'_> {
+    pub fn is_away(&self) -> bool {
+        self.0.starts_with('G')
+    }
+
+    pub fn is_oper(&self) -> bool {
+        self.0.contains('*')
+    }
+
+    pub fn is_bot(&self) -> bool {
+        self.0.contains('B')
+    }
+
+    pub fn status(&self) -> impl Iterator<Item = char> + '_ {
+        self.0.chars().filter(|c| "~&@%+".contains(*c))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Reply<'a> {
+    pub channel: &'a str,
+    pub username: &'a str,
+    pub host: &'a str,
+    pub server: &'a str,
+    pub nickname: &'a str,
+    pub flags: WhoFlags<'a>,
+    pub hopcount: u32,
+    pub realname: &'a str,
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Reply<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw<'a> {
+            channel: &'a str,
+            username: &'a str,
+            host: &'a str,
+            server: &'a str,
+            nickname: &'a str,
+            flags: WhoFlags<'a>,
+            trailing: &'a str,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        let (hopcount, realname) = raw.trailing.split_once(' ').unwrap_or((raw.trailing, ""));
+
+        Ok(Self {
+            channel: raw.channel,
+            username: raw.username,
+            host: raw.host,
+            server: raw.server,
+            nickname: raw.nickname,
+            flags: raw.flags,
+            hopcount: hopcount.parse().map_err(de::Error::custom)?,
+            realname,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{proto::numeric, Message};
+
+    #[test]
+    fn who_reply() {
+        let msg = Message::from(
+            ":irc.example.com 352 rini #irk ~alice example.com irc.example.com alice H@ :0 Alice Liddell",
+        );
+        assert_eq!(
+            numeric::Reply::decode(&msg),
+            Some(Ok(numeric::Reply::WhoReply(Reply {
+                channel: "#irk",
+                username: "~alice",
+                host: "example.com",
+                server: "irc.example.com",
+                nickname: "alice",
+                flags: WhoFlags("H@"),
+                hopcount: 0,
+                realname: "Alice Liddell",
+            })))
+        );
+
+        let msg = Message::from(
+            ":irc.example.com 352 rini * alice example.com irc.example.com alice G :x y",
+        );
+        assert!(matches!(numeric::Reply::decode(&msg), Some(Err(_))));
+    }
+
+    #[test]
+    fn flags() {
+        let flags = WhoFlags("H@");
+        assert!(!flags.is_away() && !flags.is_oper() && !flags.is_bot());
+        assert!(flags.status().eq(['@']));
+
+        let flags = WhoFlags("G*");
+        assert!(flags.is_away() && flags.is_oper());
+        assert_eq!(flags.status().next(), None);
+
+        let flags = WhoFlags("H+B");
+        assert!(!flags.is_away() && flags.is_bot());
+        assert!(flags.status().eq(['+']));
+    }
+}
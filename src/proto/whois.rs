@@ -0,0 +1,89 @@
+use std::{collections::HashMap, time::Duration};
+
+use super::numeric::Reply;
+
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Whois {
+    pub nickname: String,
+    pub ip: Option<String>,
+    pub idle: Option<Duration>,
+    pub signon: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Accumulator {
+    pending: HashMap<String, Whois>,
+}
+
+impl Accumulator {
+    pub fn feed(&mut self, reply: &Reply) -> Option<Whois> {
+        let nickname = match reply {
+            Reply::WhoisIdle { nickname, .. } | Reply::WhoisActually { nickname, .. } => nickname,
+            Reply::EndOfWhois { nickname } => {
+                let whois = self.pending.remove(*nickname);
+                return Some(whois.unwrap_or_else(|| Whois {
+                    nickname: nickname.to_string(),
+                    ..Default::default()
+                }));
+            }
+            _ => return None,
+        };
+
+        let whois = self.pending.entry(nickname.to_string()).or_default();
+        whois.nickname = nickname.to_string();
+        match *reply {
+            Reply::WhoisIdle { idle, signon, .. } => {
+                whois.idle = Some(idle);
+                whois.signon = Some(signon);
+            }
+            Reply::WhoisActually { ip, .. } => whois.ip = Some(ip.into()),
+            _ => {}
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    #[test]
+    fn idle() {
+        let msg = Message::from(
+            ":irc.example.com 317 rini alice 120 1700000000 :seconds idle, signon time",
+        );
+        assert_eq!(
+            Reply::decode(&msg),
+            Some(Ok(Reply::WhoisIdle {
+                nickname: "alice",
+                idle: Duration::from_secs(120),
+                signon: 1700000000,
+            }))
+        );
+    }
+
+    #[test]
+    fn accumulate() {
+        let mut whois = Accumulator::default();
+        let mut done = None;
+        for line in [
+            ":irc.example.com 338 rini alice 192.0.2.1 :actually using host",
+            ":irc.example.com 317 rini alice 120 1700000000 :seconds idle, signon time",
+            ":irc.example.com 318 rini alice :End of /WHOIS list",
+        ] {
+            let reply = Reply::decode(&Message::from(line)).unwrap().unwrap();
+            done = whois.feed(&reply);
+        }
+
+        assert_eq!(
+            done,
+            Some(Whois {
+                nickname: "alice".into(),
+                ip: Some("192.0.2.1".into()),
+                idle: Some(Duration::from_secs(120)),
+                signon: Some(1700000000),
+            })
+        );
+    }
+}